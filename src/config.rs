@@ -0,0 +1,847 @@
+use crate::font::FontFace;
+use crate::hotplug::Module;
+use crate::layout::{CanvasLayout, Layout, Placement, Target};
+use crate::metric::{Metric, MetricSource};
+use crate::notify::NotificationConfig;
+use crate::scheduler::Schedule;
+use crate::widget::{
+    AllCPUsWidget, BatteryDetails, BatteryWidget, BatteryWidgetUgly, CPUWidget, ClockOptions, ClockStyle,
+    ClockView, ClockWidget, CommandLine, CommandOutput, CommandWidget, CriticalStyle, HourFormat, SecondsDisplay,
+    DiskWidget, HistoryWidget, LoadWidget, TaskCount, NetworkScale, NetworkStyle, NetworkWidget, RAMWidget,
+    ScrollDirection, TemperatureWidget, TextSource, TextWidget, UpdatableWidget,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, fs, path::Path, time::Duration};
+
+/// Width of a single LED matrix module, in pixels
+pub const MODULE_WIDTH: usize = 9;
+/// Height of a single LED matrix module, in pixels
+pub const MODULE_HEIGHT: usize = 34;
+
+///
+/// Top level layout of the JSON configuration file
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Brightness applied to every module on startup (0=OFF, 255=FULL).
+    /// Left untouched if not specified.
+    #[serde(default)]
+    pub brightness: Option<u8>,
+
+    /// Time between updates, in milliseconds, for widgets that don't set their
+    /// own interval. Each widget's built-in interval is used if not specified.
+    #[serde(default)]
+    pub update_period: Option<u64>,
+
+    /// Names for modules, mapped to the serial number (or port name) of the module
+    /// they refer to, e.g. `{"left": "FRAKDEAM0012345678"}`
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+
+    /// Put several modules together into one larger canvas for widgets to span
+    #[serde(default)]
+    pub canvas: Option<CanvasConfig>,
+
+    /// Play an animation over the widgets when a desktop notification arrives
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+
+    /// Every widget to display, and where to put it
+    pub widgets: Vec<WidgetConfig>,
+}
+
+///
+/// Which modules make up the canvas, and how they are arranged
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CanvasConfig {
+    #[serde(default)]
+    pub arrangement: Arrangement,
+
+    /// The modules making up the canvas, from left to right or top to bottom.
+    /// Defaults to the first two modules found.
+    #[serde(default = "default_canvas_modules")]
+    pub modules: Vec<ModuleSelector>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Arrangement {
+    /// Modules next to each other, e.g. 18x34 for two modules
+    #[default]
+    SideBySide,
+    /// Modules on top of each other, e.g. 9x68 for two modules
+    Stacked,
+}
+
+///
+/// Placement of a single widget: what it is, which module it goes on and where
+///
+#[derive(Deserialize)]
+#[serde(try_from = "RawWidgetConfig")]
+pub struct WidgetConfig {
+    pub kind: WidgetKind,
+
+    /// The module to draw on. Defaults to the canvas if there is one, otherwise
+    /// the first module found.
+    #[serde(default)]
+    pub module: Option<ModuleSelector>,
+
+    /// Column of the widget's top left corner. Can be negative if clip is set.
+    #[serde(default)]
+    pub x: i32,
+
+    /// Row of the widget's top left corner. Can be negative if clip is set.
+    #[serde(default)]
+    pub y: i32,
+
+    /// Allow the widget to hang off the edge, only drawing the part that fits
+    #[serde(default)]
+    pub clip: bool,
+
+    /// Time between updates of this widget, in milliseconds
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+///
+/// A widget entry as written in the file. serde ignores deny_unknown_fields
+/// through flatten, so the widget's own options are collected here and parsed
+/// separately, which lets a misspelt option be reported instead of dropped.
+///
+#[derive(Deserialize)]
+struct RawWidgetConfig {
+    #[serde(flatten)]
+    kind: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    module: Option<ModuleSelector>,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    clip: bool,
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+impl TryFrom<RawWidgetConfig> for WidgetConfig {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawWidgetConfig) -> Result<WidgetConfig, serde_json::Error> {
+        Ok(WidgetConfig {
+            kind: serde_json::from_value(serde_json::Value::Object(raw.kind))?,
+            module: raw.module,
+            x: raw.x,
+            y: raw.y,
+            clip: raw.clip,
+            interval: raw.interval,
+        })
+    }
+}
+
+///
+/// Every widget that can be placed from the config file, along with its options.
+/// Selected with the "type" key, e.g. `{"type": "all_cpus", "merge_threads": true}`
+///
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum WidgetKind {
+    Battery {
+        /// Add a row showing hours until empty, or until full while charging
+        #[serde(default)]
+        show_time: bool,
+        /// Add a row showing the charge or discharge rate
+        #[serde(default)]
+        show_rate: bool,
+    },
+    BatteryUgly {
+        #[serde(default)]
+        show_time: bool,
+        #[serde(default)]
+        show_rate: bool,
+    },
+    Ram {},
+    Cpu {
+        #[serde(default = "default_bar_height")]
+        bar_height: usize,
+    },
+    AllCpus {
+        #[serde(default)]
+        merge_threads: bool,
+        /// Number of columns with merge_threads, otherwise the length of each row
+        #[serde(default)]
+        width: Option<usize>,
+        /// Number of rows, otherwise the height of each column with merge_threads
+        #[serde(default)]
+        height: Option<usize>,
+    },
+    Clock {
+        #[serde(default)]
+        format: HourFormat,
+        #[serde(default)]
+        seconds: SecondsDisplay,
+        #[serde(default)]
+        view: ClockView,
+        #[serde(default)]
+        style: ClockStyle,
+        /// Timezone name, e.g. "Europe/Berlin". Defaults to the system's local time.
+        #[serde(default)]
+        timezone: Option<chrono_tz::Tz>,
+    },
+    Disk {
+        /// Mount points (e.g. "/home") or device names (e.g. "nvme0n1p2") to show.
+        /// Every disk mounted at startup is shown if empty, as many as fit on a module.
+        #[serde(default)]
+        disks: Vec<String>,
+        #[serde(default = "default_bar_height")]
+        bar_height: usize,
+    },
+    Network {
+        /// Interface to show (e.g. "wlan0"). Every non-loopback interface is
+        /// added together if not given.
+        #[serde(default)]
+        interface: Option<String>,
+        #[serde(default)]
+        style: NetworkStyle,
+        #[serde(default)]
+        scale: NetworkScale,
+        /// Rows per bar, or the height of the graph
+        #[serde(default)]
+        height: Option<usize>,
+    },
+    Load {
+        #[serde(default = "default_bar_height")]
+        bar_height: usize,
+        /// Count processes or threads below the load bars
+        #[serde(default)]
+        count: TaskCount,
+    },
+    Temperature {
+        /// Sensor labels to show, one bar each (e.g. "cpu" matches "k10temp Tctl").
+        /// Only the hottest sensor is shown if empty.
+        #[serde(default)]
+        sensors: Vec<String>,
+        /// Temperature shown as an empty bar, in degrees C
+        #[serde(default = "default_temperature_min")]
+        min: f32,
+        /// Temperature shown as a full bar, in degrees C
+        #[serde(default = "default_temperature_max")]
+        max: f32,
+        /// Temperature above which a bar is highlighted, in degrees C. Each
+        /// sensor's own critical temperature is used if not given.
+        #[serde(default)]
+        critical: Option<f32>,
+        #[serde(default)]
+        critical_style: CriticalStyle,
+        /// Add a dimmer bar per fan showing its speed (Linux only)
+        #[serde(default)]
+        fans: bool,
+        /// Fan speed shown as a full bar, in RPM
+        #[serde(default = "default_fan_max")]
+        fan_max: f32,
+        #[serde(default = "default_bar_height")]
+        bar_height: usize,
+    },
+    Text {
+        /// Where the text comes from: `{"text": "..."}`, `{"file": "path"}` or `"stdin"`
+        source: TextSource,
+        #[serde(default)]
+        direction: ScrollDirection,
+        #[serde(default)]
+        font: FontFace,
+        /// Blank columns between letters
+        #[serde(default = "default_text_spacing")]
+        spacing: usize,
+        /// Size of the widget along the direction it scrolls. Defaults to the
+        /// width or height of a module.
+        #[serde(default)]
+        length: Option<usize>,
+        /// Blank pixels between repeats of the text. Defaults to the length, so
+        /// the text scrolls all the way off before coming back.
+        #[serde(default)]
+        gap: Option<usize>,
+        /// Pixels scrolled per second
+        #[serde(default = "default_text_speed")]
+        speed: f32,
+    },
+    History {
+        metric: Metric,
+        /// Interface to show for network metrics. Every non-loopback interface
+        /// is added together if not given.
+        #[serde(default)]
+        interface: Option<String>,
+        /// Number of samples shown, one per column
+        #[serde(default = "default_graph_width")]
+        width: usize,
+        #[serde(default = "default_graph_height")]
+        height: usize,
+    },
+    Command {
+        /// A shell command line, or a list of the program and its arguments
+        command: CommandLine,
+        #[serde(default)]
+        output: CommandOutput,
+        /// Number drawn as an empty bar
+        #[serde(default)]
+        min: f32,
+        /// Number drawn as a full bar
+        #[serde(default = "default_command_max")]
+        max: f32,
+        /// Milliseconds the program can run for before it's killed
+        #[serde(default = "default_command_timeout")]
+        timeout: u64,
+        /// Font for text output
+        #[serde(default)]
+        font: FontFace,
+        /// Blank columns between letters of text output
+        #[serde(default = "default_text_spacing")]
+        spacing: usize,
+        /// Defaults to the width of a module
+        #[serde(default)]
+        width: Option<usize>,
+        /// Defaults to 1 row for numbers, the font height for text, and 9 rows for pixels
+        #[serde(default)]
+        height: Option<usize>,
+    },
+}
+
+///
+/// Which module a widget is drawn on, either by its position in the detected
+/// list (e.g. `0`) or by name. A name can be an alias from the "modules" map,
+/// a USB serial number, or a serial port name (e.g. `"/dev/ttyACM0"` or `"COM3"`).
+///
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum ModuleSelector {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),
+    Write(std::io::Error),
+    Parse(serde_json::Error),
+    OutOfBounds {
+        widget: String,
+        x: i32,
+        y: i32,
+        width: usize,
+        height: usize,
+        surface: String,
+    },
+    Overlap {
+        first: String,
+        second: String,
+        surface: String,
+    },
+    /// An update interval of 0, which would redraw as fast as possible. Holds
+    /// which setting it was.
+    ZeroInterval(String),
+}
+
+fn default_bar_height() -> usize {
+    1
+}
+
+fn default_temperature_min() -> f32 {
+    30.0
+}
+
+fn default_temperature_max() -> f32 {
+    100.0
+}
+
+fn default_fan_max() -> f32 {
+    6000.0
+}
+
+fn default_text_spacing() -> usize {
+    1
+}
+
+fn default_text_speed() -> f32 {
+    8.0
+}
+
+fn default_graph_width() -> usize {
+    9
+}
+
+fn default_graph_height() -> usize {
+    8
+}
+
+fn default_command_max() -> f32 {
+    100.0
+}
+
+fn default_command_timeout() -> u64 {
+    2000
+}
+
+fn default_canvas_modules() -> Vec<ModuleSelector> {
+    vec![ModuleSelector::Index(0), ModuleSelector::Index(1)]
+}
+
+impl Default for ModuleSelector {
+    fn default() -> Self {
+        ModuleSelector::Index(0)
+    }
+}
+
+impl fmt::Display for ModuleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleSelector::Index(i) => write!(f, "module {}", i),
+            ModuleSelector::Name(n) => write!(f, "module {}", n),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Unable to read config file: {}", e),
+            ConfigError::Write(e) => write!(f, "Unable to write config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Invalid config file: {}", e),
+            ConfigError::OutOfBounds {
+                widget,
+                x,
+                y,
+                width,
+                height,
+                surface,
+            } => write!(
+                f,
+                "Widget '{}' ({}x{} at x={}, y={}) does not fit on the {}. Set \"clip\": true to allow it",
+                widget, width, height, x, y, surface
+            ),
+            ConfigError::Overlap {
+                first,
+                second,
+                surface,
+            } => write!(
+                f,
+                "Widgets '{}' and '{}' overlap on {}",
+                first, second, surface
+            ),
+            ConfigError::ZeroInterval(setting) => {
+                write!(f, "The {} must be at least 1 millisecond", setting)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for Config {
+    ///
+    /// The layout used when no config file is given
+    ///
+    fn default() -> Self {
+        let widget = |kind, y| WidgetConfig {
+            kind,
+            module: None,
+            x: 0,
+            y,
+            clip: false,
+            interval: None,
+        };
+
+        Config {
+            brightness: None,
+            update_period: None,
+            modules: BTreeMap::new(),
+            canvas: None,
+            notifications: None,
+            widgets: vec![
+                widget(
+                    WidgetKind::BatteryUgly {
+                        show_time: false,
+                        show_rate: false,
+                    },
+                    0,
+                ),
+                widget(WidgetKind::Ram {}, 3),
+                // One row per thread, so it runs off the bottom on CPUs with
+                // more than 17 threads
+                WidgetConfig {
+                    clip: true,
+                    ..widget(
+                        WidgetKind::AllCpus {
+                            merge_threads: false,
+                            width: None,
+                            height: None,
+                        },
+                        6,
+                    )
+                },
+                widget(
+                    WidgetKind::Clock {
+                        format: HourFormat::default(),
+                        seconds: SecondsDisplay::default(),
+                        view: ClockView::default(),
+                        style: ClockStyle::default(),
+                        timezone: None,
+                    },
+                    23,
+                ),
+            ],
+        }
+    }
+}
+
+impl Config {
+    ///
+    /// Read and parse a JSON config file
+    ///
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Read)?;
+        serde_json::from_str(&contents).map_err(ConfigError::Parse)
+    }
+
+    ///
+    /// Create every widget in the config. Fails if an update interval is 0, or a
+    /// widget hangs off the edge of a module or the canvas, or covers another widget
+    /// on one of the modules found so far.
+    ///
+    pub fn build_layout(&self, modules: &[Module]) -> Result<Layout, ConfigError> {
+        let mut placements: Vec<Placement> = Vec::new();
+
+        if self.update_period == Some(0) {
+            return Err(ConfigError::ZeroInterval("update_period".to_string()));
+        }
+
+        for wc in self.widgets.iter() {
+            if wc.interval == Some(0) {
+                let setting = format!("interval of widget '{}'", wc.kind.name());
+                return Err(ConfigError::ZeroInterval(setting));
+            }
+
+            let target = match (&wc.module, &self.canvas) {
+                (Some(sel), _) => Target::Module(self.expand_alias(sel)),
+                (None, Some(_)) => Target::Canvas,
+                (None, None) => Target::Module(ModuleSelector::default()),
+            };
+
+            let widget = wc.kind.build();
+            let interval = match wc.interval.or(self.update_period) {
+                Some(ms) => Duration::from_millis(ms),
+                None => widget.update_interval(),
+            };
+
+            placements.push(Placement {
+                widget,
+                name: wc.kind.name(),
+                target,
+                x: wc.x,
+                y: wc.y,
+                clip: wc.clip,
+                overflow_reported: false,
+                schedule: Schedule::new(interval),
+            });
+        }
+
+        let layout = Layout {
+            placements,
+            canvas: self.canvas.as_ref().map(|c| CanvasLayout {
+                arrangement: c.arrangement,
+                modules: c.modules.iter().map(|m| self.expand_alias(m)).collect(),
+            }),
+        };
+        layout.validate(modules)?;
+
+        Ok(layout)
+    }
+
+    ///
+    /// Swap an alias for the module it refers to, so it can be matched against
+    /// the serial numbers and port names of the modules found
+    ///
+    fn expand_alias(&self, sel: &ModuleSelector) -> ModuleSelector {
+        match sel {
+            ModuleSelector::Name(n) => ModuleSelector::Name(self.modules.get(n).unwrap_or(n).clone()),
+            sel => sel.clone(),
+        }
+    }
+
+    ///
+    /// Save a module alias to a config file, leaving the rest of the file as is
+    ///
+    pub fn save_alias(path: &Path, alias: &str, target: &str) -> Result<(), ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Read)?;
+        let mut json: serde_json::Value =
+            serde_json::from_str(&contents).map_err(ConfigError::Parse)?;
+
+        let modules = json
+            .as_object_mut()
+            .map(|o| o.entry("modules").or_insert_with(|| serde_json::json!({})));
+        match modules.and_then(|m| m.as_object_mut()) {
+            Some(m) => {
+                m.insert(alias.to_string(), target.into());
+            }
+            None => {
+                return Err(ConfigError::Parse(serde::de::Error::custom(
+                    "expected \"modules\" to be an object",
+                )))
+            }
+        }
+
+        let out = serde_json::to_string_pretty(&json).map_err(ConfigError::Parse)?;
+        fs::write(path, out + "\n").map_err(ConfigError::Write)
+    }
+}
+
+impl WidgetKind {
+    ///
+    /// The name used for this widget in the config file
+    ///
+    pub fn name(&self) -> &'static str {
+        match self {
+            WidgetKind::Battery { .. } => "battery",
+            WidgetKind::BatteryUgly { .. } => "battery_ugly",
+            WidgetKind::Ram {} => "ram",
+            WidgetKind::Cpu { .. } => "cpu",
+            WidgetKind::AllCpus { .. } => "all_cpus",
+            WidgetKind::Clock { .. } => "clock",
+            WidgetKind::Disk { .. } => "disk",
+            WidgetKind::Network { .. } => "network",
+            WidgetKind::Load { .. } => "load",
+            WidgetKind::Temperature { .. } => "temperature",
+            WidgetKind::Text { .. } => "text",
+            WidgetKind::History { .. } => "history",
+            WidgetKind::Command { .. } => "command",
+        }
+    }
+
+    ///
+    /// Create the widget described by this config entry
+    ///
+    pub fn build(&self) -> Box<dyn UpdatableWidget> {
+        match self {
+            WidgetKind::Battery {
+                show_time,
+                show_rate,
+            } => Box::new(BatteryWidget::new(BatteryDetails {
+                time: *show_time,
+                rate: *show_rate,
+            })),
+            WidgetKind::BatteryUgly {
+                show_time,
+                show_rate,
+            } => Box::new(BatteryWidgetUgly::new(BatteryDetails {
+                time: *show_time,
+                rate: *show_rate,
+            })),
+            WidgetKind::Ram {} => Box::new(RAMWidget::new()),
+            WidgetKind::Cpu { bar_height } => Box::new(CPUWidget::new(*bar_height)),
+            WidgetKind::AllCpus {
+                merge_threads,
+                width,
+                height,
+            } => Box::new(AllCPUsWidget::new(*merge_threads, *width, *height)),
+            WidgetKind::Clock {
+                format,
+                seconds,
+                view,
+                style,
+                timezone,
+            } => Box::new(ClockWidget::new(ClockOptions {
+                format: *format,
+                seconds: *seconds,
+                view: *view,
+                style: *style,
+                timezone: *timezone,
+            })),
+            WidgetKind::Disk { disks, bar_height } => {
+                Box::new(DiskWidget::new(disks.clone(), *bar_height))
+            }
+            WidgetKind::Network {
+                interface,
+                style,
+                scale,
+                height,
+            } => {
+                let default_height = match style {
+                    NetworkStyle::Bars => 1,
+                    NetworkStyle::Graph => 8,
+                };
+                Box::new(NetworkWidget::new(
+                    interface.clone(),
+                    *style,
+                    *scale,
+                    height.unwrap_or(default_height),
+                ))
+            }
+            WidgetKind::Load { bar_height, count } => {
+                Box::new(LoadWidget::new(*bar_height, *count))
+            }
+            WidgetKind::Temperature {
+                sensors,
+                min,
+                max,
+                critical,
+                critical_style,
+                fans,
+                fan_max,
+                bar_height,
+            } => Box::new(TemperatureWidget::new(
+                sensors.clone(),
+                *min,
+                *max,
+                *critical,
+                *critical_style,
+                *fans,
+                *fan_max,
+                *bar_height,
+            )),
+            WidgetKind::Text {
+                source,
+                direction,
+                font,
+                spacing,
+                length,
+                gap,
+                speed,
+            } => Box::new(TextWidget::new(
+                source.clone(),
+                *direction,
+                *font,
+                *spacing,
+                *length,
+                *gap,
+                *speed,
+            )),
+            WidgetKind::History {
+                metric,
+                interface,
+                width,
+                height,
+            } => Box::new(HistoryWidget::new(
+                MetricSource::new(*metric, interface.clone()),
+                *width,
+                *height,
+            )),
+            WidgetKind::Command {
+                command,
+                output,
+                min,
+                max,
+                timeout,
+                font,
+                spacing,
+                width,
+                height,
+            } => Box::new(CommandWidget::new(
+                command.clone(),
+                *output,
+                *min,
+                *max,
+                Duration::from_millis(*timeout),
+                *font,
+                *spacing,
+                *width,
+                *height,
+            )),
+        }
+    }
+}
+
+impl ModuleSelector {
+    ///
+    /// Find the index of the module this selector refers to, if it has been seen
+    ///
+    pub fn resolve(&self, modules: &[Module]) -> Option<usize> {
+        match self {
+            ModuleSelector::Index(i) if *i < modules.len() => Some(*i),
+            ModuleSelector::Index(_) => None,
+            ModuleSelector::Name(n) => modules
+                .iter()
+                .position(|m| m.serial_number() == Some(n.as_str()))
+                .or_else(|| modules.iter().position(|m| &m.port_info.port_name == n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(config: &str) -> Result<Layout, ConfigError> {
+        serde_json::from_str::<Config>(config).map_err(ConfigError::Parse)?.build_layout(&[])
+    }
+
+    #[test]
+    fn unknown_options() {
+        let parse = |config: &str| serde_json::from_str::<Config>(config).err().unwrap().to_string();
+        assert!(parse(r#"{"widgets": [], "refresh": 100}"#).contains("unknown field `refresh`"));
+        assert!(parse(r#"{"widgets": [{"type": "ram", "colour": 1}]}"#).contains("unknown field `colour`"));
+        assert!(parse(r#"{"widgets": [{"type": "cpu", "height": 2}]}"#).contains("unknown field `height`"));
+        assert!(parse(r#"{"widgets": [{"type": "fan"}]}"#).contains("unknown variant `fan`"));
+    }
+
+    #[test]
+    fn overlap() {
+        let err = build(r#"{"widgets": [{"type": "ram", "y": 4}, {"type": "cpu", "y": 5}]}"#).err().unwrap();
+        assert!(matches!(err, ConfigError::Overlap { ref first, ref second, .. } if first == "ram" && second == "cpu"));
+
+        // Touching edges and separate modules are fine
+        assert!(build(r#"{"widgets": [{"type": "ram", "y": 4}, {"type": "ram", "y": 6}]}"#).is_ok());
+        assert!(build(r#"{"widgets": [{"type": "ram"}, {"type": "ram", "module": 1}]}"#).is_ok());
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        for (x, y) in [(-1, 0), (0, -1), (1, 0), (0, 33)] {
+            let config = format!(r#"{{"widgets": [{{"type": "ram", "x": {x}, "y": {y}}}]}}"#);
+            let err = build(&config).err().unwrap();
+            assert!(matches!(err, ConfigError::OutOfBounds { x: ex, y: ey, width: 9, height: 2, .. } if (ex, ey) == (x, y)));
+        }
+        assert!(build(r#"{"widgets": [{"type": "ram", "y": 32}]}"#).is_ok());
+
+        let canvas = r#"{"canvas": {"modules": [0, 1]}, "widgets": [{"type": "ram", "x": 9, "y": 32}]}"#;
+        assert!(build(canvas).is_ok());
+        let canvas = r#"{"canvas": {"modules": [0, 1]}, "widgets": [{"type": "ram", "x": 10, "y": 32}]}"#;
+        assert!(matches!(build(canvas), Err(ConfigError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn overlap_on_module_named_two_ways() {
+        use crate::hotplug::ModuleManager;
+        use crate::ledmatrix::LedMatrix;
+
+        let mats = vec![LedMatrix::new_virtual("virtual0").0, LedMatrix::new_virtual("virtual1").0];
+        let manager = ModuleManager::with_modules(mats, None);
+        let build = |config: &str| {
+            serde_json::from_str::<Config>(config).unwrap().build_layout(manager.modules())
+        };
+
+        let by_alias = r#"{"modules": {"left": "virtual0"}, "widgets": [
+            {"type": "ram", "module": "left", "y": 20}, {"type": "ram", "module": 0, "y": 20}]}"#;
+        assert!(matches!(build(by_alias), Err(ConfigError::Overlap { .. })));
+
+        let on_canvas = r#"{"canvas": {"modules": ["virtual0", 1]}, "widgets": [
+            {"type": "ram", "y": 20}, {"type": "ram", "module": 0, "y": 20}]}"#;
+        assert!(matches!(build(on_canvas), Err(ConfigError::Overlap { .. })));
+
+        let other_module = r#"{"canvas": {"modules": ["virtual0", 1]}, "widgets": [
+            {"type": "ram", "y": 20}, {"type": "ram", "module": "virtual1", "y": 20}]}"#;
+        assert!(build(other_module).is_ok());
+    }
+
+    #[test]
+    fn zero_interval() {
+        let err = build(r#"{"widgets": [{"type": "ram", "interval": 0}]}"#).err().unwrap();
+        assert!(matches!(err, ConfigError::ZeroInterval(ref s) if s == "interval of widget 'ram'"));
+
+        let err = build(r#"{"update_period": 0, "widgets": [{"type": "ram"}]}"#).err().unwrap();
+        assert!(matches!(err, ConfigError::ZeroInterval(ref s) if s == "update_period"));
+
+        assert!(build(r#"{"update_period": 0, "widgets": []}"#).is_err());
+        assert!(build(r#"{"widgets": [{"type": "ram", "interval": 1}]}"#).is_ok());
+    }
+}
@@ -0,0 +1,682 @@
+use crate::notify::Urgency;
+use crate::overlay::{Image, ImageFormat};
+use clap::{Args, Subcommand};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// How long a client has to send its command, and the daemon has to answer it
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Commands the running daemon accepts on its control socket. Each is sent as
+/// a single line, e.g. "brightness 40".
+///
+#[derive(Subcommand, Clone, PartialEq, Debug)]
+pub enum ControlCommand {
+    /// Stop updating widgets, leaving the modules showing the last frame
+    Pause,
+    /// Start updating widgets again
+    Resume,
+    /// Set the brightness of every module (0=OFF, 255=FULL)
+    Brightness { value: u8 },
+    /// Re-read the config file and rebuild the layout
+    Reload,
+    /// Switch to the layout in another config file
+    Layout { config: PathBuf },
+    /// Put every module to sleep
+    Sleep,
+    /// Wake every module up and redraw it
+    Wake,
+    /// Show what the daemon is doing
+    Status,
+    /// Draw an image over the widgets until it times out
+    Frame(FrameArgs),
+    /// Remove an image drawn with "frame", or every image if no id is given
+    Clear { id: Option<String> },
+    /// Play the notification animation, as if a notification had arrived
+    Notify {
+        /// How urgent the notification is, checked against the config's "urgency"
+        #[arg(long, value_enum, default_value_t = Urgency::Normal)]
+        urgency: Urgency,
+        /// App the notification is from, checked against "apps" and "ignore_apps"
+        #[arg(default_value = "")]
+        app: String,
+    },
+}
+
+///
+/// Where and how long to draw an image sent with the frame command. On the
+/// socket these are sent as "key=value" pairs after "frame", followed by the
+/// image itself, e.g. "frame format=pgm priority=5 timeout=3000".
+///
+#[derive(Args, Clone, PartialEq, Debug)]
+pub struct FrameArgs {
+    /// Image file to send, or "-" to read it from stdin
+    #[arg(long, default_value = "-")]
+    pub file: PathBuf,
+
+    /// How the image is encoded. Guessed from the file extension if not given,
+    /// otherwise raw.
+    #[arg(long, value_enum)]
+    pub format: Option<ImageFormat>,
+
+    /// Module to draw on, by index, serial number, port name or config name
+    #[arg(long, default_value = "0")]
+    pub module: String,
+
+    /// Column of the image's top left corner
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub x: i32,
+
+    /// Row of the image's top left corner
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub y: i32,
+
+    /// Width of a raw image
+    #[arg(long, default_value_t = 9)]
+    pub width: usize,
+
+    /// Height of a raw image
+    #[arg(long, default_value_t = 34)]
+    pub height: usize,
+
+    /// Images with a higher priority are drawn over lower ones
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub priority: i32,
+
+    /// Milliseconds to show the image for, 0 to show it until it's cleared
+    #[arg(long, default_value_t = 10_000)]
+    pub timeout: u64,
+
+    /// Name for the image, so it can be replaced or cleared later
+    #[arg(long)]
+    pub id: Option<String>,
+
+    /// The image, once it has been read from the socket
+    #[arg(skip)]
+    pub image: Option<Image>,
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Pause => write!(f, "pause"),
+            ControlCommand::Resume => write!(f, "resume"),
+            ControlCommand::Brightness { value } => write!(f, "brightness {}", value),
+            ControlCommand::Reload => write!(f, "reload"),
+            ControlCommand::Layout { config } => write!(f, "layout {}", config.display()),
+            ControlCommand::Sleep => write!(f, "sleep"),
+            ControlCommand::Wake => write!(f, "wake"),
+            ControlCommand::Status => write!(f, "status"),
+            ControlCommand::Frame(args) => {
+                write!(
+                    f,
+                    "frame format={} module={} x={} y={} width={} height={} priority={} timeout={}",
+                    args.format.unwrap_or(ImageFormat::Raw),
+                    args.module,
+                    args.x,
+                    args.y,
+                    args.width,
+                    args.height,
+                    args.priority,
+                    args.timeout
+                )?;
+                match args.id {
+                    Some(ref id) => write!(f, " id={}", id),
+                    None => Ok(()),
+                }
+            }
+            ControlCommand::Clear { id: Some(id) } => write!(f, "clear {}", id),
+            ControlCommand::Clear { id: None } => write!(f, "clear"),
+            ControlCommand::Notify { urgency, app } => write!(f, "notify {} {}", urgency, app),
+        }
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<ControlCommand, String> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(' ') {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+
+        match (name, arg) {
+            ("pause", "") => Ok(ControlCommand::Pause),
+            ("resume", "") => Ok(ControlCommand::Resume),
+            ("brightness", value) => match value.parse() {
+                Ok(value) => Ok(ControlCommand::Brightness { value }),
+                Err(_) => Err(format!("Expected a brightness from 0 to 255, got \"{}\"", value)),
+            },
+            ("reload", "") => Ok(ControlCommand::Reload),
+            ("layout", "") => Err("Expected a config file to switch to".to_string()),
+            ("layout", path) => Ok(ControlCommand::Layout {
+                config: PathBuf::from(path),
+            }),
+            ("sleep", "") => Ok(ControlCommand::Sleep),
+            ("wake", "") => Ok(ControlCommand::Wake),
+            ("status", "") => Ok(ControlCommand::Status),
+            ("frame", params) => parse_frame(params).map(ControlCommand::Frame),
+            ("clear", "") => Ok(ControlCommand::Clear { id: None }),
+            ("clear", id) => Ok(ControlCommand::Clear {
+                id: Some(id.to_string()),
+            }),
+            // The app name goes last, as it can have spaces in it
+            ("notify", params) => {
+                let (urgency, app) = params.split_once(' ').unwrap_or((params, ""));
+                Ok(ControlCommand::Notify {
+                    urgency: match urgency {
+                        "" => Urgency::Normal,
+                        urgency => urgency.parse()?,
+                    },
+                    app: app.trim().to_string(),
+                })
+            }
+            _ => Err(format!("Unknown command \"{}\"", line)),
+        }
+    }
+}
+
+///
+/// Parse the "key=value" pairs after "frame". Anything not given is left at the
+/// same default as the ctl command line.
+///
+fn parse_frame(params: &str) -> Result<FrameArgs, String> {
+    let mut args = FrameArgs {
+        file: PathBuf::from("-"),
+        format: Some(ImageFormat::Raw),
+        module: "0".to_string(),
+        x: 0,
+        y: 0,
+        width: 9,
+        height: 34,
+        priority: 0,
+        timeout: 10_000,
+        id: None,
+        image: None,
+    };
+
+    for param in params.split_whitespace() {
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got \"{}\"", param))?;
+        let bad_value = || format!("Invalid value for {}: \"{}\"", key, value);
+        match key {
+            "format" => args.format = Some(value.parse()?),
+            "module" => args.module = value.to_string(),
+            "x" => args.x = value.parse().map_err(|_| bad_value())?,
+            "y" => args.y = value.parse().map_err(|_| bad_value())?,
+            "width" => args.width = value.parse().map_err(|_| bad_value())?,
+            "height" => args.height = value.parse().map_err(|_| bad_value())?,
+            "priority" => args.priority = value.parse().map_err(|_| bad_value())?,
+            "timeout" => args.timeout = value.parse().map_err(|_| bad_value())?,
+            "id" => args.id = Some(value.to_string()),
+            _ => return Err(format!("Unknown frame option \"{}\"", key)),
+        }
+    }
+
+    Ok(args)
+}
+
+///
+/// A command received on the control socket, waiting for the daemon to answer it
+///
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: Sender<Result<String, String>>,
+}
+
+impl ControlRequest {
+    ///
+    /// A command for the main loop, along with where its reply will turn up
+    ///
+    pub fn new(command: ControlCommand) -> (ControlRequest, Receiver<Result<String, String>>) {
+        let (reply, response) = mpsc::channel();
+        (ControlRequest { command, reply }, response)
+    }
+
+    ///
+    /// Send the result back to the client. Ok replies can carry extra lines of
+    /// information, e.g. for status.
+    ///
+    pub fn respond(self, result: Result<String, String>) {
+        // The client may have given up waiting, there's nobody to tell if so
+        let _ = self.reply.send(result);
+    }
+}
+
+///
+/// Listens on the control socket in the background, passing each command on to
+/// be handled by the main loop
+///
+pub struct ControlServer {
+    #[cfg(unix)]
+    path: PathBuf,
+}
+
+///
+/// Where the daemon listens by default: a socket in the user's runtime directory,
+/// or a named pipe on Windows
+///
+pub fn default_endpoint() -> String {
+    #[cfg(unix)]
+    {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let name = match std::env::var("USER") {
+            Ok(user) => format!("ledmatrix_widgets-{}.sock", user),
+            Err(_) => "ledmatrix_widgets.sock".to_string(),
+        };
+        dir.join(name).to_string_lossy().into_owned()
+    }
+    #[cfg(windows)]
+    {
+        match std::env::var("USERNAME") {
+            Ok(user) => format!(r"\\.\pipe\ledmatrix_widgets-{}", user),
+            Err(_) => r"\\.\pipe\ledmatrix_widgets".to_string(),
+        }
+    }
+}
+
+impl ControlServer {
+    ///
+    /// Start listening for commands, sending them on to the main loop through
+    /// requests. A leftover socket from a daemon that has exited is replaced,
+    /// but one that's still in use is an error.
+    ///
+    #[cfg(unix)]
+    pub fn bind(endpoint: &str, requests: Sender<ControlRequest>) -> io::Result<ControlServer> {
+        let path = PathBuf::from(endpoint);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = stream.set_read_timeout(Some(CONTROL_TIMEOUT));
+                serve_in_background(stream, &requests);
+            }
+        });
+
+        Ok(ControlServer { path })
+    }
+
+    ///
+    /// Start listening for commands on a named pipe. A pipe that's in use by
+    /// another instance is an error.
+    ///
+    #[cfg(windows)]
+    pub fn bind(endpoint: &str, requests: Sender<ControlRequest>) -> io::Result<ControlServer> {
+        // The first instance of the pipe is made here, so it's known to be ours
+        let mut waiting = pipe::create(endpoint, true).map_err(|e| {
+            match e.raw_os_error() {
+                Some(pipe::ERROR_ACCESS_DENIED) => io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", endpoint),
+                ),
+                _ => e,
+            }
+        })?;
+
+        // Each client gets the instance it connected to, and a new one is made
+        // for the next client
+        let endpoint = endpoint.to_string();
+        thread::spawn(move || loop {
+            let connected = pipe::accept(&waiting);
+            let next = match pipe::create(&endpoint, false) {
+                Ok(next) => next,
+                Err(e) => {
+                    println!("Control socket closed: {}", e);
+                    return;
+                }
+            };
+            let client = std::mem::replace(&mut waiting, next);
+            if connected.is_ok() {
+                serve_in_background(client, &requests);
+            }
+        });
+
+        Ok(ControlServer {})
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+///
+/// Serve a client on its own thread, so a slow client doesn't hold up the ones
+/// after it, and one that trips a bug only loses its own connection
+///
+fn serve_in_background<S: Read + Write + Send + 'static>(stream: S, requests: &Sender<ControlRequest>) {
+    let requests = requests.clone();
+    thread::spawn(move || serve(stream, &requests));
+}
+
+///
+/// Read one command from a client, pass it to the main loop and write back the
+/// reply: "ok" followed by any extra lines, or "error: <reason>"
+///
+fn serve<S: Read + Write>(stream: S, requests: &Sender<ControlRequest>) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+
+    // Images follow straight after the frame command
+    let parsed = line.parse::<ControlCommand>().and_then(|command| match command {
+        ControlCommand::Frame(mut args) => {
+            let format = args.format.unwrap_or(ImageFormat::Raw);
+            args.image = Some(
+                Image::read(&mut reader, format, args.width, args.height).map_err(|e| {
+                    match e.kind() {
+                        // The client stopped sending before the end of the image
+                        io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::UnexpectedEof => "The image was incomplete".to_string(),
+                        _ => format!("Unable to read the image: {}", e),
+                    }
+                })?,
+            );
+            Ok(ControlCommand::Frame(args))
+        }
+        command => Ok(command),
+    });
+
+    let result = match parsed {
+        Ok(command) => {
+            let (request, response) = ControlRequest::new(command);
+            match requests.send(request) {
+                Ok(()) => response
+                    .recv_timeout(CONTROL_TIMEOUT)
+                    .unwrap_or_else(|_| Err("The daemon didn't respond".to_string())),
+                Err(_) => Err("The daemon is shutting down".to_string()),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let text = match result {
+        Ok(info) if info.is_empty() => "ok\n".to_string(),
+        Ok(info) => format!("ok\n{}\n", info),
+        Err(e) => format!("error: {}\n", e),
+    };
+    let _ = reader.get_mut().write_all(text.as_bytes());
+}
+
+///
+/// Send a command to the running daemon, along with any data that follows it
+/// (e.g. a frame's image), returning its reply. A reply from the daemon saying
+/// the command failed is returned as an error.
+///
+pub fn send(endpoint: &str, command: &ControlCommand, payload: &[u8]) -> Result<String, String> {
+    #[cfg(unix)]
+    let stream = UnixStream::connect(endpoint).and_then(|stream| {
+        stream.set_read_timeout(Some(CONTROL_TIMEOUT * 2))?;
+        Ok(stream)
+    });
+    #[cfg(windows)]
+    let stream = pipe::connect(endpoint);
+
+    let mut stream = stream.map_err(|e| {
+        format!("Unable to connect to the daemon at {}: {}", endpoint, e)
+    })?;
+
+    let written = stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .and_then(|_| stream.write_all(payload));
+
+    // The daemon can turn down a command before all of it has been sent, e.g. an
+    // image that's too big, so the reply is read even if writing failed
+    let mut reply = String::new();
+    let read = stream.read_to_string(&mut reply);
+    if reply.is_empty() {
+        return Err(match written.and(read.map(|_| ())) {
+            Err(e) => format!("Unable to talk to the daemon: {}", e),
+            Ok(()) => "The daemon closed the connection without replying".to_string(),
+        });
+    }
+
+    match reply.split_once('\n') {
+        Some(("ok", info)) => Ok(info.trim_end().to_string()),
+        _ => Err(reply
+            .trim_end()
+            .strip_prefix("error: ")
+            .unwrap_or(reply.trim_end())
+            .to_string()),
+    }
+}
+
+///
+/// Just enough of the Windows named pipe API for the control socket. Pipes are
+/// made without a security descriptor, so they get the default one: only this
+/// user, administrators and the system can send commands, and other machines
+/// are turned away.
+///
+#[cfg(windows)]
+mod pipe {
+    use std::{
+        ffi::c_void,
+        fs::{File, OpenOptions},
+        io,
+        os::windows::io::{AsRawHandle, FromRawHandle, RawHandle},
+        ptr, thread,
+        time::Duration,
+    };
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x0000_0003;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    /// A blocking byte stream (all zero flags), from local clients only
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x0000_0008;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const BUFFER_SIZE: u32 = 4096;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+    const ERROR_PIPE_BUSY: i32 = 231;
+    /// Returned when the first instance of a pipe is made while another process has it
+    pub const ERROR_ACCESS_DENIED: i32 = 5;
+
+    /// How many times a client tries a pipe whose instances are all busy
+    const CONNECT_ATTEMPTS: usize = 50;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *const c_void,
+        ) -> RawHandle;
+        fn ConnectNamedPipe(pipe: RawHandle, overlapped: *mut c_void) -> i32;
+    }
+
+    ///
+    /// Make a new instance of a pipe for a client to connect to. The first
+    /// instance fails if another process already has the pipe.
+    ///
+    pub fn create(name: &str, first: bool) -> io::Result<File> {
+        let name: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        let open_mode = match first {
+            true => PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            false => PIPE_ACCESS_DUPLEX,
+        };
+
+        // SAFETY: the name is a null terminated UTF-16 string that outlives the call
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                ptr::null(),
+            )
+        };
+        // INVALID_HANDLE_VALUE
+        if handle as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the handle is valid and nothing else owns it
+        Ok(unsafe { File::from_raw_handle(handle) })
+    }
+
+    ///
+    /// Wait for a client to connect to an instance of a pipe
+    ///
+    pub fn accept(pipe: &File) -> io::Result<()> {
+        // SAFETY: the handle is a pipe instance that's open for as long as the borrow
+        if unsafe { ConnectNamedPipe(pipe.as_raw_handle(), ptr::null_mut()) } != 0 {
+            return Ok(());
+        }
+
+        // A client that connects before ConnectNamedPipe is called is still connected
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(ERROR_PIPE_CONNECTED) => Ok(()),
+            _ => Err(e),
+        }
+    }
+
+    ///
+    /// Connect to a pipe, waiting a little if another client has just taken the
+    /// only free instance
+    ///
+    pub fn connect(name: &str) -> io::Result<File> {
+        let mut attempts = 1;
+        loop {
+            match OpenOptions::new().read(true).write(true).open(name) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && attempts < CONNECT_ATTEMPTS => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(20));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: Option<&str>) -> FrameArgs {
+        FrameArgs {
+            file: PathBuf::from("-"),
+            format: Some(ImageFormat::Pgm),
+            module: "left".to_string(),
+            x: -3,
+            y: -10,
+            width: 12,
+            height: 5,
+            priority: -2,
+            timeout: 0,
+            id: id.map(str::to_string),
+            image: None,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let commands = [
+            ControlCommand::Pause,
+            ControlCommand::Resume,
+            ControlCommand::Brightness { value: 40 },
+            ControlCommand::Reload,
+            ControlCommand::Layout {
+                config: PathBuf::from("/home/me/my layouts/work.json"),
+            },
+            ControlCommand::Sleep,
+            ControlCommand::Wake,
+            ControlCommand::Status,
+            ControlCommand::Frame(frame(None)),
+            ControlCommand::Frame(frame(Some("alert"))),
+            ControlCommand::Clear { id: None },
+            ControlCommand::Clear {
+                id: Some("alert".to_string()),
+            },
+            ControlCommand::Notify {
+                urgency: Urgency::Critical,
+                app: "Google Chrome".to_string(),
+            },
+            ControlCommand::Notify {
+                urgency: Urgency::Low,
+                app: String::new(),
+            },
+        ];
+
+        for command in commands {
+            let line = command.to_string();
+            assert_eq!(line.parse::<ControlCommand>(), Ok(command), "{}", line);
+        }
+    }
+
+    #[test]
+    fn frame_defaults() {
+        let ControlCommand::Frame(args) = "frame".parse().unwrap() else {
+            panic!("expected a frame command");
+        };
+        assert_eq!(args.format, Some(ImageFormat::Raw));
+        assert_eq!((args.module.as_str(), args.x, args.y), ("0", 0, 0));
+        assert_eq!((args.width, args.height), (9, 34));
+        assert_eq!((args.priority, args.timeout, args.id), (0, 10_000, None));
+    }
+
+    #[test]
+    fn notify_defaults() {
+        let normal = ControlCommand::Notify {
+            urgency: Urgency::Normal,
+            app: String::new(),
+        };
+        assert_eq!("notify".parse(), Ok(normal));
+    }
+
+    #[test]
+    fn invalid() {
+        for line in [
+            "",
+            "dance",
+            "pause now",
+            "brightness 256",
+            "brightness",
+            "layout",
+            "frame x",
+            "frame x=left",
+            "frame width=-1",
+            "frame colour=red",
+            "frame format=png",
+            "notify urgent",
+        ] {
+            assert!(line.parse::<ControlCommand>().is_err(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use crate::config::{Config, ModuleSelector};
+use crate::control::{ControlCommand, ControlRequest, ControlServer};
+use crate::hotplug::ModuleManager;
+use crate::layout::Layout;
+use crate::notify::{self, NotificationConfig};
+use crate::overlay::{self, Overlay, Overlays};
+use crate::preview;
+use crate::scheduler;
+use crate::transport::VirtualHandle;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+/// How long to sleep when there are no widgets and no modules to look for
+const IDLE_PERIOD: Duration = Duration::from_secs(1);
+
+///
+/// Everything the default program needs while it runs: the widgets, the modules
+/// they're drawn on, and whatever the control socket has asked for
+///
+pub struct Daemon {
+    layout: Layout,
+    modules: ModuleManager,
+    /// The config file the layout came from, or None for the built-in layout
+    config_path: Option<PathBuf>,
+    /// Module names from the config file, for images sent to a named module
+    aliases: BTreeMap<String, String>,
+    /// Images from other programs, drawn over the widgets
+    overlays: Overlays,
+    /// Virtual modules to draw in the terminal, if previewing
+    previews: Option<Vec<VirtualHandle>>,
+    /// Commands from the control socket and the notification watcher
+    requests: Receiver<ControlRequest>,
+    /// Handed out to anything that sends commands to the main loop
+    requester: Sender<ControlRequest>,
+    /// Kept so the socket is removed when the daemon is dropped
+    _control: Option<ControlServer>,
+    /// What to do when a notification arrives, or None to ignore them
+    notifications: Option<NotificationConfig>,
+    /// Set once the notification bus is being watched, which lasts until exit
+    watching: bool,
+    paused: bool,
+    /// Set when something other than a widget means the modules need redrawing
+    redraw: bool,
+}
+
+impl Daemon {
+    pub fn new(
+        layout: Layout,
+        modules: ModuleManager,
+        aliases: BTreeMap<String, String>,
+        config_path: Option<PathBuf>,
+        previews: Option<Vec<VirtualHandle>>,
+        notifications: Option<NotificationConfig>,
+    ) -> Daemon {
+        let (requester, requests) = mpsc::channel();
+        let mut daemon = Daemon {
+            layout,
+            modules,
+            config_path,
+            aliases,
+            overlays: Overlays::default(),
+            previews,
+            requests,
+            requester,
+            _control: None,
+            notifications,
+            watching: false,
+            paused: false,
+            redraw: false,
+        };
+        daemon.watch_notifications();
+        daemon
+    }
+
+    ///
+    /// Start taking commands from the control socket
+    ///
+    pub fn listen(&mut self, endpoint: &str) -> io::Result<()> {
+        self._control = Some(ControlServer::bind(endpoint, self.requester.clone())?);
+        Ok(())
+    }
+
+    ///
+    /// Start watching for notifications if the config asks for them and they
+    /// aren't already being watched
+    ///
+    fn watch_notifications(&mut self) {
+        if self.notifications.is_some() && !self.watching {
+            notify::watch(self.requester.clone());
+            self.watching = true;
+        }
+    }
+
+    ///
+    /// Update and draw widgets forever, answering commands from the control
+    /// socket in between
+    ///
+    pub fn run(&mut self) -> ! {
+        if self.previews.is_some() {
+            print!("{}", preview::clear());
+        }
+
+        loop {
+            // New modules need drawing even if no widget has changed
+            let connected = self.modules.poll();
+            // Widgets naming a new module in different ways may turn out to overlap
+            if connected {
+                if let Err(e) = self.layout.validate(self.modules.modules()) {
+                    println!("{}", e);
+                }
+            }
+            let changed =
+                !self.paused && scheduler::tick(&mut self.layout.placements, Instant::now());
+            let animated = self.overlays.tick(Instant::now());
+
+            // Images from other programs are still drawn while paused
+            if changed || connected || animated || self.redraw {
+                self.redraw = false;
+                let frames = self.layout.compose(self.modules.modules(), &self.overlays);
+                for (idx, frame) in frames.into_iter().enumerate() {
+                    self.modules.draw(idx, frame);
+                }
+
+                if let Some(ref previews) = self.previews {
+                    print!("{}", preview::render(previews));
+                    std::io::stdout().flush().unwrap();
+                }
+            }
+
+            // Sleep until the next widget is due, an image changes or times out, it's time
+            // to look for modules, or a command comes in
+            let next_due = match self.paused {
+                true => None,
+                false => scheduler::next_due(&self.layout.placements),
+            };
+            let wake = [next_due, self.overlays.next_change(), self.modules.next_scan()]
+                .into_iter()
+                .flatten()
+                .min()
+                .unwrap_or_else(|| Instant::now() + IDLE_PERIOD);
+            let timeout = wake.saturating_duration_since(Instant::now());
+
+            // The daemon holds a sender itself, so this only ever times out
+            if let Ok(request) = self.requests.recv_timeout(timeout) {
+                let result = self.handle(&request.command);
+                request.respond(result);
+            }
+        }
+    }
+
+    ///
+    /// Carry out a command from the control socket
+    ///
+    fn handle(&mut self, command: &ControlCommand) -> Result<String, String> {
+        match command {
+            ControlCommand::Pause => self.paused = true,
+            ControlCommand::Resume => self.paused = false,
+            ControlCommand::Brightness { value } => self.modules.set_brightness(*value),
+            ControlCommand::Reload => self.switch_layout(self.config_path.clone())?,
+            ControlCommand::Layout { config } => self.switch_layout(Some(config.clone()))?,
+            ControlCommand::Sleep => self.modules.set_sleeping(true),
+            ControlCommand::Wake => self.modules.set_sleeping(false),
+            ControlCommand::Status => return Ok(self.status()),
+            ControlCommand::Frame(args) => {
+                let image = args.image.clone().ok_or("No image was sent")?;
+                let module = match args.module.parse() {
+                    Ok(idx) => ModuleSelector::Index(idx),
+                    Err(_) => self.expand_alias(&ModuleSelector::Name(args.module.clone())),
+                };
+                self.overlays.push(Overlay::still(
+                    args.id.clone(),
+                    module,
+                    args.x,
+                    args.y,
+                    args.priority,
+                    image,
+                    overlay::expiry(args.timeout, Instant::now()),
+                ));
+            }
+            ControlCommand::Clear { id } => {
+                if self.overlays.clear(id.as_deref()) == 0 && id.is_some() {
+                    return Err(format!("No image with the id \"{}\"", id.as_ref().unwrap()));
+                }
+            }
+            ControlCommand::Notify { urgency, app } => {
+                let config = self
+                    .notifications
+                    .as_ref()
+                    .ok_or("Notifications aren't set up in the config file")?;
+                if !config.accepts(app, *urgency) {
+                    return Ok("The notification was filtered out".to_string());
+                }
+
+                // Every module if none are given
+                let targets: Vec<ModuleSelector> = match config.modules.is_empty() {
+                    true => (0..self.modules.len()).map(ModuleSelector::Index).collect(),
+                    false => config.modules.iter().map(|m| self.expand_alias(m)).collect(),
+                };
+                let (x, y, frames) = config.animation();
+                let now = Instant::now();
+                for (idx, module) in targets.into_iter().enumerate() {
+                    // A new notification restarts the animation rather than adding another
+                    self.overlays.push(Overlay {
+                        id: Some(format!("notification-{}", idx)),
+                        module,
+                        x,
+                        y,
+                        priority: config.priority,
+                        frames: frames.clone(),
+                        frame_period: Duration::from_millis(config.frame_period.max(1)),
+                        shown: now,
+                        expires: Some(now + Duration::from_millis(config.duration)),
+                    });
+                }
+            }
+        }
+
+        self.redraw = true;
+        Ok(String::new())
+    }
+
+    ///
+    /// Swap a module name from the config file for the module it refers to
+    ///
+    fn expand_alias(&self, sel: &ModuleSelector) -> ModuleSelector {
+        match sel {
+            ModuleSelector::Name(n) => ModuleSelector::Name(self.aliases.get(n).unwrap_or(n).clone()),
+            sel => sel.clone(),
+        }
+    }
+
+    ///
+    /// Load a config file (or the built-in layout) and start drawing it. The
+    /// current layout is kept if the new one can't be loaded.
+    ///
+    fn switch_layout(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let config = match path {
+            Some(ref path) => Config::load(path).map_err(|e| e.to_string())?,
+            None => Config::default(),
+        };
+        self.layout = config.build_layout(self.modules.modules()).map_err(|e| e.to_string())?;
+        self.aliases = config.modules;
+        if let Some(brightness) = config.brightness {
+            self.modules.set_brightness(brightness);
+        }
+        self.notifications = config.notifications;
+        self.watch_notifications();
+
+        println!(
+            "Switched to the layout in {}",
+            path.as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or("the built-in config".to_string())
+        );
+        self.config_path = path;
+        Ok(())
+    }
+
+    ///
+    /// A few lines describing what's being drawn, and on which modules
+    ///
+    fn status(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "state: {}",
+                match (self.paused, self.modules.is_sleeping()) {
+                    (_, true) => "sleeping",
+                    (true, false) => "paused",
+                    (false, false) => "running",
+                }
+            ),
+            format!(
+                "config: {}",
+                self.config_path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or("built-in".to_string())
+            ),
+            format!(
+                "brightness: {}",
+                self.modules
+                    .brightness()
+                    .map(|b| b.to_string())
+                    .unwrap_or("unchanged".to_string())
+            ),
+            format!(
+                "widgets: {}",
+                self.layout
+                    .placements
+                    .iter()
+                    .map(|p| p.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!("images: {}", self.overlays.len()),
+            format!("modules: {}", self.modules.len()),
+        ];
+
+        for (idx, module) in self.modules.modules().iter().enumerate() {
+            lines.push(format!(
+                "  {}: {} - {} - Serial: {}",
+                idx,
+                module.port_info.port_name,
+                if module.is_connected() { "connected" } else { "disconnected" },
+                module.serial_number().unwrap_or("unknown")
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledmatrix::LedMatrix;
+
+    ///
+    /// A daemon drawing a config on two virtual modules, without watching the
+    /// real session bus for notifications
+    ///
+    fn daemon(config: &str) -> Daemon {
+        let config: Config = serde_json::from_str(config).unwrap();
+        let mats = vec![LedMatrix::new_virtual("virtual0").0, LedMatrix::new_virtual("virtual1").0];
+        let mut daemon = Daemon::new(
+            config.build_layout(&[]).unwrap(),
+            ModuleManager::with_modules(mats, None),
+            config.modules,
+            None,
+            None,
+            None,
+        );
+        daemon.notifications = config.notifications;
+        daemon
+    }
+
+    #[test]
+    fn notification_on_aliased_module() {
+        let mut daemon = daemon(
+            r#"{"widgets": [], "modules": {"right": "virtual1"}, "notifications": {"modules": ["right"]}}"#,
+        );
+        let notify = ControlCommand::Notify { urgency: notify::Urgency::Normal, app: String::new() };
+        assert_eq!(daemon.handle(&notify), Ok(String::new()));
+
+        daemon.overlays.tick(Instant::now());
+        let frames = daemon.layout.compose(daemon.modules.modules(), &daemon.overlays);
+        assert_eq!(frames[0], [[0; 9]; 34]);
+        assert_eq!(frames[1], [[255; 9]; 34]);
+    }
+
+    #[test]
+    #[ignore = "starts a private dbus-daemon"]
+    #[cfg(feature = "notifications")]
+    fn notification_queues_overlay() {
+        use std::{
+            collections::HashMap,
+            io::{BufRead, BufReader},
+            process::{Command, Stdio},
+            thread,
+        };
+        use zbus::{blocking::connection::Builder, message::Flags, zvariant::Value, Message};
+
+        let mut bus = Command::new("dbus-daemon")
+            .args(["--session", "--print-address=1", "--nofork", "--nopidfile"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("dbus-daemon should be installed");
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap()).read_line(&mut address).unwrap();
+
+        let mut daemon = daemon(
+            r#"{"widgets": [], "modules": {"left": "virtual0"}, "notifications": {"animation": "icon", "modules": ["left", 1]}}"#,
+        );
+        let monitor = Builder::address(address.trim()).unwrap().build().unwrap();
+        let requester = daemon.requester.clone();
+        thread::spawn(move || notify::watch_bus(&monitor, &requester));
+
+        // Stands in for the notification server, so the call has somewhere to go
+        let client = Builder::address(address.trim()).unwrap().build().unwrap();
+        client.request_name("org.freedesktop.Notifications").unwrap();
+        let hints = HashMap::from([("urgency", Value::U8(2))]);
+        let body = ("Thunderbird", 0u32, "", "New mail", "", Vec::<&str>::new(), hints, -1i32);
+
+        // The monitor may not be set up yet, so keep sending until it sees one
+        let mut request = None;
+        for _ in 0..50 {
+            let notify = Message::method_call("/org/freedesktop/Notifications", "Notify")
+                .unwrap()
+                .destination("org.freedesktop.Notifications")
+                .unwrap()
+                .interface("org.freedesktop.Notifications")
+                .unwrap()
+                .with_flags(Flags::NoReplyExpected)
+                .unwrap()
+                .build(&body)
+                .unwrap();
+            client.send(&notify).unwrap();
+            if let Ok(r) = daemon.requests.recv_timeout(Duration::from_millis(100)) {
+                request = Some(r);
+                break;
+            }
+        }
+        bus.kill().unwrap();
+        bus.wait().unwrap();
+
+        let request = request.expect("the notification should reach the main loop");
+        match request.command {
+            ControlCommand::Notify { urgency, ref app } => {
+                assert_eq!(urgency, notify::Urgency::Critical);
+                assert_eq!(app, "Thunderbird");
+            }
+            _ => panic!("expected a notify command"),
+        }
+        assert_eq!(daemon.handle(&request.command), Ok(String::new()));
+        // One animation per module, and both are drawn
+        assert_eq!(daemon.overlays.len(), 2);
+        daemon.overlays.tick(Instant::now());
+        let frames = daemon.layout.compose(daemon.modules.modules(), &daemon.overlays);
+        assert!(frames.iter().all(|frame| frame.iter().flatten().any(|&px| px == 255)));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::ledmatrix::{Game, GameKey, LedMatrix, LedMatrixError, LifeStart, Pattern, PwmFreq};
+use clap::Subcommand;
+use std::time::Duration;
+
+///
+/// Commands sent straight to a module's firmware. Commands that read a setting
+/// back print it on its own, so they're easy to use from scripts.
+///
+#[derive(Subcommand, Clone, PartialEq, Debug)]
+pub enum FirmwareCommand {
+    /// Print the firmware version
+    Version,
+    /// Set the brightness of every LED (0=OFF, 255=FULL), or print it if not given
+    Brightness { value: Option<u8> },
+    /// Put the module to sleep or wake it up, or print whether it's asleep if not given
+    Sleep { sleeping: Option<bool> },
+    /// Start or stop scrolling whatever is shown, or print whether it's scrolling if not given
+    Animate { animate: Option<bool> },
+    /// Stop scrolling, e.g. the animation shown at startup
+    StopAnimation,
+    /// Set the milliseconds between steps of scrolling or of a game, or print it if not given
+    AnimationPeriod { ms: Option<u16> },
+    /// Set the PWM frequency the LEDs are driven at, or print it if not given
+    PwmFreq {
+        #[arg(value_enum)]
+        freq: Option<PwmFreq>,
+    },
+    /// Draw a pattern built into the firmware
+    Pattern {
+        #[arg(value_enum)]
+        pattern: Pattern,
+    },
+    /// Fill the module from the bottom up to a percentage of its height
+    Percentage { percent: u8 },
+    /// Start a game built into the firmware
+    Game {
+        #[arg(value_enum)]
+        game: Game,
+        /// What the game of life starts from
+        #[arg(long, value_enum, default_value_t = LifeStart::CurrentMatrix)]
+        start: LifeStart,
+    },
+    /// Press a key in the running game
+    Key {
+        #[arg(value_enum)]
+        key: GameKey,
+    },
+    /// Reboot the module into its bootloader to update the firmware
+    Bootloader,
+    /// Crash the firmware on purpose, for testing. Unplug the module to recover.
+    Panic,
+}
+
+impl FirmwareCommand {
+    ///
+    /// Send the command to a module, returning anything it read back
+    ///
+    pub fn run(&self, mat: &mut LedMatrix) -> Result<String, LedMatrixError> {
+        let mut info = String::new();
+        match self {
+            FirmwareCommand::Version => info = mat.get_fw_version()?,
+            FirmwareCommand::Brightness { value: Some(value) } => mat.set_full_brightness(*value)?,
+            FirmwareCommand::Brightness { value: None } => info = mat.get_brightness()?.to_string(),
+            FirmwareCommand::Sleep { sleeping: Some(true) } => mat.sleep()?,
+            FirmwareCommand::Sleep { sleeping: Some(false) } => mat.wake()?,
+            FirmwareCommand::Sleep { sleeping: None } => info = mat.is_sleeping()?.to_string(),
+            FirmwareCommand::Animate { animate: Some(animate) } => mat.set_animate(*animate)?,
+            FirmwareCommand::Animate { animate: None } => info = mat.is_animating()?.to_string(),
+            FirmwareCommand::StopAnimation => mat.stop_animation()?,
+            FirmwareCommand::AnimationPeriod { ms: Some(ms) } => {
+                mat.set_animation_period(Duration::from_millis(*ms as u64))?
+            }
+            FirmwareCommand::AnimationPeriod { ms: None } => {
+                info = mat.get_animation_period()?.as_millis().to_string()
+            }
+            FirmwareCommand::PwmFreq { freq: Some(freq) } => mat.set_pwm_freq(*freq)?,
+            FirmwareCommand::PwmFreq { freq: None } => info = mat.get_pwm_freq()?.to_string(),
+            FirmwareCommand::Pattern { pattern } => mat.draw_pattern(*pattern)?,
+            FirmwareCommand::Percentage { percent } => mat.draw_percentage(*percent)?,
+            FirmwareCommand::Game { game, start } => mat.start_game(*game, *start)?,
+            FirmwareCommand::Key { key } => mat.game_control(*key)?,
+            FirmwareCommand::Bootloader => mat.reset_to_bootloader()?,
+            FirmwareCommand::Panic => mat.trigger_panic()?,
+        }
+
+        Ok(info)
+    }
+}
@@ -0,0 +1,197 @@
+use crate::widget::{Shape, OFF, ON_DIM, ON_FULL};
+use serde::Deserialize;
+
+///
+/// A bitmap font face. Every glyph is the same height, but glyphs can be narrower
+/// than the face's width (e.g. "." and ":"), so text isn't spaced out more than it
+/// needs to be.
+///
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum FontFace {
+    /// Small 3x5 glyphs, the same digits as the clock
+    #[default]
+    #[serde(rename = "3x5")]
+    Small,
+    /// Larger 5x7 glyphs, for text that should be readable from further away
+    #[serde(rename = "5x7")]
+    Large,
+}
+
+impl FontFace {
+    ///
+    /// Height of every glyph in the face
+    ///
+    pub fn height(&self) -> usize {
+        match self {
+            FontFace::Small => 5,
+            FontFace::Large => 7,
+        }
+    }
+
+    ///
+    /// Rows of a glyph, with '#' lit, '+' dimmed and '.' off. Letters are
+    /// uppercase only, so lowercase letters are drawn as uppercase, and
+    /// anything missing from the face is drawn as '?'.
+    ///
+    fn glyph(&self, c: char) -> &'static [&'static str] {
+        let c = c.to_ascii_uppercase();
+        match self {
+            FontFace::Small => glyph_3x5(c).unwrap_or_else(|| glyph_3x5('?').unwrap()),
+            FontFace::Large => glyph_5x7(c).unwrap_or_else(|| glyph_5x7('?').unwrap()),
+        }
+    }
+}
+
+///
+/// Render a line of text, with a number of blank columns between glyphs.
+/// Returns the size of the text along with its pixels, row by row.
+///
+pub fn render_text(text: &str, face: FontFace, spacing: usize) -> (Shape, Vec<u8>) {
+    let glyphs: Vec<&[&str]> = text.chars().map(|c| face.glyph(c)).collect();
+    let height = face.height();
+    let width = glyphs.iter().map(|g| g[0].len()).sum::<usize>()
+        + spacing * glyphs.len().saturating_sub(1);
+
+    let mut out = vec![OFF; width * height];
+    let mut x = 0;
+    for glyph in glyphs {
+        for (y, row) in glyph.iter().enumerate() {
+            for (gx, px) in row.chars().enumerate() {
+                out[y * width + x + gx] = match px {
+                    '#' => ON_FULL,
+                    '+' => ON_DIM,
+                    _ => OFF,
+                };
+            }
+        }
+        x += glyph[0].len() + spacing;
+    }
+
+    (Shape { x: width, y: height }, out)
+}
+
+// ================ Glyphs ================
+
+#[rustfmt::skip]
+fn glyph_3x5(c: char) -> Option<&'static [&'static str]> {
+    Some(match c {
+        '0' => &[".#.", "#.#", "#.#", "#.#", ".#."],
+        '1' => &["..#", ".+#", "..#", "..#", "..#"],
+        '2' => &["###", "..#", "###", "#..", "###"],
+        '3' => &["###", "..#", "###", "..#", "###"],
+        '4' => &["#.#", "#.#", "###", "..#", "..#"],
+        '5' => &["###", "#..", "###", "..#", "###"],
+        '6' => &[".#+", "#..", "###", "#.#", "###"],
+        '7' => &["###", "+.#", "..#", ".#.", ".#."],
+        '8' => &["###", "#.#", "###", "#.#", "###"],
+        '9' => &["###", "#.#", "###", "..#", "+#."],
+        'A' => &[".#.", "#.#", "###", "#.#", "#.#"],
+        'B' => &["##.", "#.#", "##.", "#.#", "##."],
+        'C' => &[".##", "#..", "#..", "#..", ".##"],
+        'D' => &["##.", "#.#", "#.#", "#.#", "##."],
+        'E' => &["###", "#..", "##.", "#..", "###"],
+        'F' => &["###", "#..", "##.", "#..", "#.."],
+        'G' => &[".##", "#..", "#.#", "#.#", ".##"],
+        'H' => &["#.#", "#.#", "###", "#.#", "#.#"],
+        'I' => &["###", ".#.", ".#.", ".#.", "###"],
+        'J' => &["..#", "..#", "..#", "#.#", ".#."],
+        'K' => &["#.#", "#.#", "##.", "#.#", "#.#"],
+        'L' => &["#..", "#..", "#..", "#..", "###"],
+        'M' => &["#.#", "###", "###", "#.#", "#.#"],
+        'N' => &["##.", "#.#", "#.#", "#.#", "#.#"],
+        'O' => &[".#.", "#.#", "#.#", "#.#", ".#."],
+        'P' => &["##.", "#.#", "##.", "#..", "#.."],
+        'Q' => &[".#.", "#.#", "#.#", "##.", ".##"],
+        'R' => &["##.", "#.#", "##.", "#.#", "#.#"],
+        'S' => &[".##", "#..", ".#.", "..#", "##."],
+        'T' => &["###", ".#.", ".#.", ".#.", ".#."],
+        'U' => &["#.#", "#.#", "#.#", "#.#", "###"],
+        'V' => &["#.#", "#.#", "#.#", "#.#", ".#."],
+        'W' => &["#.#", "#.#", "###", "###", "#.#"],
+        'X' => &["#.#", "#.#", ".#.", "#.#", "#.#"],
+        'Y' => &["#.#", "#.#", ".#.", ".#.", ".#."],
+        'Z' => &["###", "..#", ".#.", "#..", "###"],
+        ' ' => &["..", "..", "..", "..", ".."],
+        '.' => &[".", ".", ".", ".", "#"],
+        ',' => &["..", "..", "..", ".#", "#."],
+        ':' => &[".", "#", ".", "#", "."],
+        '\'' => &["#", "#", ".", ".", "."],
+        '!' => &["#", "#", "#", ".", "#"],
+        '?' => &["###", "..#", ".#.", "...", ".#."],
+        '-' => &["...", "...", "###", "...", "..."],
+        '+' => &["...", ".#.", "###", ".#.", "..."],
+        '=' => &["...", "###", "...", "###", "..."],
+        '_' => &["...", "...", "...", "...", "###"],
+        '*' => &["#.#", ".#.", "#.#", "...", "..."],
+        '/' => &["..#", "..#", ".#.", "#..", "#.."],
+        '%' => &["#.#", "..#", ".#.", "#..", "#.#"],
+        '(' => &[".#", "#.", "#.", "#.", ".#"],
+        ')' => &["#.", ".#", ".#", ".#", "#."],
+        '<' => &["..#", ".#.", "#..", ".#.", "..#"],
+        '>' => &["#..", ".#.", "..#", ".#.", "#.."],
+        '°' => &[".#.", "#.#", ".#.", "...", "..."],
+        _ => return None,
+    })
+}
+
+#[rustfmt::skip]
+fn glyph_5x7(c: char) -> Option<&'static [&'static str]> {
+    Some(match c {
+        '0' => &[".###.", "#...#", "#..##", "#.#.#", "##..#", "#...#", ".###."],
+        '1' => &["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."],
+        '2' => &[".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"],
+        '3' => &["#####", "...#.", "..#..", "...#.", "....#", "#...#", ".###."],
+        '4' => &["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."],
+        '5' => &["#####", "#....", "####.", "....#", "....#", "#...#", ".###."],
+        '6' => &["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."],
+        '7' => &["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."],
+        '8' => &[".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."],
+        '9' => &[".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."],
+        'A' => &[".###.", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"],
+        'B' => &["####.", "#...#", "#...#", "####.", "#...#", "#...#", "####."],
+        'C' => &[".###.", "#...#", "#....", "#....", "#....", "#...#", ".###."],
+        'D' => &["###..", "#..#.", "#...#", "#...#", "#...#", "#..#.", "###.."],
+        'E' => &["#####", "#....", "#....", "####.", "#....", "#....", "#####"],
+        'F' => &["#####", "#....", "#....", "####.", "#....", "#....", "#...."],
+        'G' => &[".###.", "#...#", "#....", "#.###", "#...#", "#...#", ".####"],
+        'H' => &["#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"],
+        'I' => &[".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###."],
+        'J' => &["..###", "...#.", "...#.", "...#.", "...#.", "#..#.", ".##.."],
+        'K' => &["#...#", "#..#.", "#.#..", "##...", "#.#..", "#..#.", "#...#"],
+        'L' => &["#....", "#....", "#....", "#....", "#....", "#....", "#####"],
+        'M' => &["#...#", "##.##", "#.#.#", "#.#.#", "#...#", "#...#", "#...#"],
+        'N' => &["#...#", "#...#", "##..#", "#.#.#", "#..##", "#...#", "#...#"],
+        'O' => &[".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."],
+        'P' => &["####.", "#...#", "#...#", "####.", "#....", "#....", "#...."],
+        'Q' => &[".###.", "#...#", "#...#", "#...#", "#.#.#", "#..#.", ".##.#"],
+        'R' => &["####.", "#...#", "#...#", "####.", "#.#..", "#..#.", "#...#"],
+        'S' => &[".####", "#....", "#....", ".###.", "....#", "....#", "####."],
+        'T' => &["#####", "..#..", "..#..", "..#..", "..#..", "..#..", "..#.."],
+        'U' => &["#...#", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."],
+        'V' => &["#...#", "#...#", "#...#", "#...#", "#...#", ".#.#.", "..#.."],
+        'W' => &["#...#", "#...#", "#...#", "#.#.#", "#.#.#", "#.#.#", ".#.#."],
+        'X' => &["#...#", "#...#", ".#.#.", "..#..", ".#.#.", "#...#", "#...#"],
+        'Y' => &["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#..", "..#.."],
+        'Z' => &["#####", "....#", "...#.", "..#..", ".#...", "#....", "#####"],
+        ' ' => &["...", "...", "...", "...", "...", "...", "..."],
+        '.' => &["..", "..", "..", "..", "..", "##", "##"],
+        ',' => &["..", "..", "..", "..", ".#", ".#", "#."],
+        ':' => &["..", "##", "##", "..", "##", "##", ".."],
+        '\'' => &["#", "#", ".", ".", ".", ".", "."],
+        '!' => &["#", "#", "#", "#", "#", ".", "#"],
+        '?' => &[".###.", "#...#", "....#", "...#.", "..#..", ".....", "..#.."],
+        '-' => &[".....", ".....", ".....", "#####", ".....", ".....", "....."],
+        '+' => &[".....", "..#..", "..#..", "#####", "..#..", "..#..", "....."],
+        '=' => &[".....", ".....", "#####", ".....", "#####", ".....", "....."],
+        '_' => &[".....", ".....", ".....", ".....", ".....", ".....", "#####"],
+        '*' => &[".....", "#.#.#", ".###.", "#####", ".###.", "#.#.#", "....."],
+        '/' => &[".....", "....#", "...#.", "..#..", ".#...", "#....", "....."],
+        '%' => &["##...", "##..#", "...#.", "..#..", ".#...", "#..##", "...##"],
+        '(' => &["..#", ".#.", "#..", "#..", "#..", ".#.", "..#"],
+        ')' => &["#..", ".#.", "..#", "..#", "..#", ".#.", "#.."],
+        '<' => &["...#", "..#.", ".#..", "#...", ".#..", "..#.", "...#"],
+        '>' => &["#...", ".#..", "..#.", "...#", "..#.", ".#..", "#..."],
+        '°' => &[".##.", "#..#", "#..#", ".##.", "....", "....", "...."],
+        _ => return None,
+    })
+}
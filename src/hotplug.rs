@@ -0,0 +1,275 @@
+use crate::ledmatrix::{self, LedMatrix, LedMatrixError};
+use crate::matrix::Matrix;
+use serialport::SerialPortInfo;
+use std::time::{Duration, Instant};
+
+/// How often to look for modules that have been plugged in or come back
+const RESCAN_PERIOD: Duration = Duration::from_secs(2);
+
+///
+/// A module that has been seen at some point while running. It keeps its place
+/// in the list while unplugged, so widgets placed by index don't move around.
+///
+pub struct Module {
+    pub port_info: SerialPortInfo,
+    matrix: Option<LedMatrix>,
+    last_frame: Option<Matrix>,
+    /// Why the module last failed to open, so the same error isn't printed on
+    /// every rescan
+    last_error: Option<String>,
+}
+
+///
+/// Keeps track of every module, reconnecting to modules that are unplugged and
+/// plugged back in, and picking up new ones as they appear.
+///
+pub struct ModuleManager {
+    modules: Vec<Module>,
+    brightness: Option<u8>,
+    /// Whether modules have been put to sleep. Frames are kept but not drawn
+    /// until they wake up.
+    sleeping: bool,
+    last_scan: Option<Instant>,
+    scanning: bool,
+}
+
+impl Module {
+    pub fn is_connected(&self) -> bool {
+        self.matrix.is_some()
+    }
+
+    pub fn serial_number(&self) -> Option<&str> {
+        ledmatrix::serial_number(&self.port_info)
+    }
+
+    ///
+    /// Whether a port belongs to this module. Matched by serial number where
+    /// possible, since a module can come back on a different port.
+    ///
+    fn owns(&self, port: &SerialPortInfo) -> bool {
+        match (self.serial_number(), ledmatrix::serial_number(port)) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.port_info.port_name == port.port_name,
+        }
+    }
+}
+
+impl ModuleManager {
+    ///
+    /// Manage real modules, found by periodically scanning the serial ports
+    ///
+    pub fn new(brightness: Option<u8>) -> ModuleManager {
+        let mut manager = ModuleManager {
+            modules: vec![],
+            brightness,
+            sleeping: false,
+            last_scan: None,
+            scanning: true,
+        };
+        manager.poll();
+        manager
+    }
+
+    ///
+    /// Manage a fixed set of modules (e.g. virtual ones) without scanning for more
+    ///
+    pub fn with_modules(mats: Vec<LedMatrix>, brightness: Option<u8>) -> ModuleManager {
+        let mut manager = ModuleManager {
+            modules: vec![],
+            brightness,
+            sleeping: false,
+            last_scan: None,
+            scanning: false,
+        };
+        for mat in mats {
+            let port_info = mat.port_info.clone();
+            manager.modules.push(Module {
+                port_info,
+                matrix: None,
+                last_frame: None,
+                last_error: None,
+            });
+            let idx = manager.modules.len() - 1;
+            manager.attach(idx, mat);
+        }
+        manager
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn brightness(&self) -> Option<u8> {
+        self.brightness
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    ///
+    /// Set the brightness of every module, including ones connected later
+    ///
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = Some(brightness);
+        for module in self.modules.iter_mut() {
+            if let Some(mat) = module.matrix.as_mut() {
+                if let Err(e) = mat.set_full_brightness(brightness) {
+                    Self::handle_error(module, e);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Put every module to sleep, or wake them up and redraw their last frame
+    ///
+    pub fn set_sleeping(&mut self, sleeping: bool) {
+        self.sleeping = sleeping;
+        for module in self.modules.iter_mut() {
+            let frame = module.last_frame;
+            if let Some(mat) = module.matrix.as_mut() {
+                let result = match (sleeping, frame) {
+                    (true, _) => mat.sleep(),
+                    (false, Some(frame)) => mat.wake().and_then(|_| mat.draw_matrix(frame)),
+                    (false, None) => mat.wake(),
+                };
+                if let Err(e) = result {
+                    Self::handle_error(module, e);
+                }
+            }
+        }
+    }
+
+    ///
+    /// The next time poll will rescan the serial ports, if it scans at all
+    ///
+    pub fn next_scan(&self) -> Option<Instant> {
+        match (self.scanning, self.last_scan) {
+            (false, _) => None,
+            (true, Some(t)) => Some(t + RESCAN_PERIOD),
+            (true, None) => Some(Instant::now()),
+        }
+    }
+
+    ///
+    /// Rescan the serial ports if it's been long enough since the last scan.
+    /// Returns true if any modules were connected.
+    ///
+    pub fn poll(&mut self) -> bool {
+        if !self.scanning || self.last_scan.is_some_and(|t| t.elapsed() < RESCAN_PERIOD) {
+            return false;
+        }
+        self.last_scan = Some(Instant::now());
+
+        let ports = match LedMatrix::find_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                println!("{}", e);
+                return false;
+            }
+        };
+
+        // Modules whose port has vanished are unplugged, even if nothing has
+        // been written to them yet
+        for module in self.modules.iter_mut() {
+            let present = ports.iter().any(|p| module.owns(p));
+            if module.is_connected() && !present {
+                Self::handle_error(module, LedMatrixError::DeviceGone);
+            }
+        }
+
+        let mut connected = false;
+        for port in ports {
+            let idx = match self.modules.iter().position(|m| m.owns(&port)) {
+                Some(idx) if self.modules[idx].is_connected() => continue,
+                Some(idx) => idx,
+                None => {
+                    self.modules.push(Module {
+                        port_info: port.clone(),
+                        matrix: None,
+                        last_frame: None,
+                        last_error: None,
+                    });
+                    self.modules.len() - 1
+                }
+            };
+
+            let module = &mut self.modules[idx];
+            module.port_info = port.clone();
+            match LedMatrix::new(port) {
+                Ok(mat) => {
+                    println!("{} - Connected", mat.port_info.port_name);
+                    module.last_error = None;
+                    self.attach(idx, mat);
+                    connected = true;
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    if module.last_error.as_ref() != Some(&error) {
+                        println!("{} - {}", module.port_info.port_name, error);
+                        module.last_error = Some(error);
+                    }
+                }
+            }
+        }
+
+        connected
+    }
+
+    ///
+    /// Draw a frame on a module. The frame is kept so it can be redrawn if the
+    /// module is reconnected.
+    ///
+    pub fn draw(&mut self, idx: usize, frame: Matrix) {
+        let module = &mut self.modules[idx];
+        module.last_frame = Some(frame);
+        if self.sleeping {
+            return;
+        }
+
+        if let Some(mat) = module.matrix.as_mut() {
+            if let Err(e) = mat.draw_matrix(frame) {
+                Self::handle_error(module, e);
+            }
+        }
+    }
+
+    ///
+    /// Start using a newly opened module, restoring its brightness and last frame,
+    /// or putting it to sleep with the others
+    ///
+    fn attach(&mut self, idx: usize, mut mat: LedMatrix) {
+        let module = &mut self.modules[idx];
+
+        let mut result = Ok(());
+        if let Some(brightness) = self.brightness {
+            result = mat.set_full_brightness(brightness);
+        }
+        if result.is_ok() && self.sleeping {
+            result = mat.sleep();
+        } else if let (Ok(()), Some(frame)) = (&result, module.last_frame) {
+            result = mat.draw_matrix(frame);
+        }
+
+        module.matrix = Some(mat);
+        if let Err(e) = result {
+            Self::handle_error(module, e);
+        }
+    }
+
+    ///
+    /// Drop the connection to a module that has gone away, it will be picked up
+    /// again on a later scan. Anything else is reported and retried next time.
+    ///
+    fn handle_error(module: &mut Module, e: LedMatrixError) {
+        println!("{} - {}", module.port_info.port_name, e);
+        if let LedMatrixError::DeviceGone = e {
+            module.matrix = None;
+        }
+    }
+}
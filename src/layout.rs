@@ -0,0 +1,216 @@
+use crate::config::{Arrangement, ConfigError, ModuleSelector, MODULE_HEIGHT, MODULE_WIDTH};
+use crate::hotplug::Module;
+use crate::matrix::{Canvas, Matrix};
+use crate::overlay::Overlays;
+use crate::scheduler::Schedule;
+use crate::widget::UpdatableWidget;
+
+///
+/// Where a widget is drawn: on a single module, or on the canvas spanning several
+///
+#[derive(Clone, PartialEq)]
+pub enum Target {
+    Module(ModuleSelector),
+    Canvas,
+}
+
+///
+/// A widget ready to be drawn. The module is looked up every time the widget is
+/// drawn, since modules can be plugged in and removed while running.
+///
+pub struct Placement {
+    pub widget: Box<dyn UpdatableWidget>,
+    /// The widget's name in the config file, for error messages
+    pub name: &'static str,
+    pub target: Target,
+    pub x: i32,
+    pub y: i32,
+    /// Whether the widget is allowed to hang off the edge
+    pub clip: bool,
+    /// Set once the widget has been reported for hanging off the edge, so it's
+    /// only reported once
+    pub overflow_reported: bool,
+    pub schedule: Schedule,
+}
+
+///
+/// Several modules put together into one larger canvas
+///
+pub struct CanvasLayout {
+    pub arrangement: Arrangement,
+    /// The modules making up the canvas, from left to right or top to bottom
+    pub modules: Vec<ModuleSelector>,
+}
+
+///
+/// Every widget to draw, and how modules are put together
+///
+pub struct Layout {
+    pub placements: Vec<Placement>,
+    pub canvas: Option<CanvasLayout>,
+}
+
+impl CanvasLayout {
+    ///
+    /// Width and height of the whole canvas
+    ///
+    pub fn size(&self) -> (usize, usize) {
+        let n = self.modules.len();
+        match self.arrangement {
+            Arrangement::SideBySide => (MODULE_WIDTH * n, MODULE_HEIGHT),
+            Arrangement::Stacked => (MODULE_WIDTH, MODULE_HEIGHT * n),
+        }
+    }
+
+    ///
+    /// Position of a module's top left corner on the canvas
+    ///
+    pub fn offset(&self, slot: usize) -> (usize, usize) {
+        match self.arrangement {
+            Arrangement::SideBySide => (MODULE_WIDTH * slot, 0),
+            Arrangement::Stacked => (0, MODULE_HEIGHT * slot),
+        }
+    }
+}
+
+impl Placement {
+    ///
+    /// Draw the widget on a canvas, reporting it the first time it unexpectedly
+    /// hangs off the edge (e.g. its size changed while running)
+    ///
+    fn draw(&mut self, canvas: &mut Canvas) {
+        if let Err(overflow) = canvas.emplace(self.widget.as_ref(), self.x, self.y) {
+            if !self.clip && !self.overflow_reported {
+                println!("Widget '{}' has been clipped: {}", self.name, overflow);
+                self.overflow_reported = true;
+            }
+        }
+    }
+}
+
+impl Layout {
+    ///
+    /// Draw every widget and split the result into one frame per module.
+    /// Widgets placed on a single module are drawn over the canvas, and images
+    /// from other programs are drawn over everything.
+    ///
+    pub fn compose(&mut self, modules: &[Module], overlays: &Overlays) -> Vec<Matrix> {
+        let mut frames = vec![Canvas::new(MODULE_WIDTH, MODULE_HEIGHT); modules.len()];
+
+        if let Some(ref cl) = self.canvas {
+            let (width, height) = cl.size();
+            let mut canvas = Canvas::new(width, height);
+            for p in self.placements.iter_mut().filter(|p| p.target == Target::Canvas) {
+                p.draw(&mut canvas);
+            }
+
+            for (slot, sel) in cl.modules.iter().enumerate() {
+                if let Some(idx) = sel.resolve(modules) {
+                    let (x, y) = cl.offset(slot);
+                    frames[idx] = canvas.crop(x, y, MODULE_WIDTH, MODULE_HEIGHT);
+                }
+            }
+        }
+
+        for p in self.placements.iter_mut() {
+            if let Target::Module(ref sel) = p.target {
+                if let Some(idx) = sel.resolve(modules) {
+                    p.draw(&mut frames[idx]);
+                }
+            }
+        }
+
+        overlays.draw(&mut frames, modules);
+        frames.iter().map(|f| f.to_matrix()).collect()
+    }
+
+    ///
+    /// Check every placed widget fits on its module or the canvas (unless it's
+    /// allowed to be clipped), and doesn't cover any other widget. A module can be
+    /// named by index, serial number or port name, so overlaps between different
+    /// names for a module are only found once it has been seen.
+    ///
+    pub fn validate(&self, modules: &[Module]) -> Result<(), ConfigError> {
+        for p in self.placements.iter().filter(|p| !p.clip) {
+            let shape = p.widget.get_shape();
+            let (width, height, surface) = match (&p.target, &self.canvas) {
+                (Target::Canvas, Some(cl)) => {
+                    let (w, h) = cl.size();
+                    (w, h, format!("{}x{} canvas", w, h))
+                }
+                _ => (
+                    MODULE_WIDTH,
+                    MODULE_HEIGHT,
+                    format!("{}x{} module", MODULE_WIDTH, MODULE_HEIGHT),
+                ),
+            };
+
+            if p.x < 0
+                || p.y < 0
+                || p.x as usize + shape.x > width
+                || p.y as usize + shape.y > height
+            {
+                return Err(ConfigError::OutOfBounds {
+                    widget: p.name.to_string(),
+                    x: p.x,
+                    y: p.y,
+                    width: shape.x,
+                    height: shape.y,
+                    surface,
+                });
+            }
+        }
+
+        // Modules that have been seen are compared by index, whatever they were called
+        let identify = |sel: &ModuleSelector| match sel.resolve(modules) {
+            Some(idx) => ModuleSelector::Index(idx),
+            None => sel.clone(),
+        };
+
+        // Widgets on a module that's part of the canvas are checked against
+        // canvas widgets as well
+        let regions: Vec<(Target, i32, i32)> = self
+            .placements
+            .iter()
+            .map(|p| match (&p.target, &self.canvas) {
+                (Target::Module(sel), Some(cl)) => {
+                    let module = identify(sel);
+                    match cl.modules.iter().position(|m| identify(m) == module) {
+                        Some(slot) => {
+                            let (ox, oy) = cl.offset(slot);
+                            (Target::Canvas, p.x + ox as i32, p.y + oy as i32)
+                        }
+                        None => (Target::Module(module), p.x, p.y),
+                    }
+                }
+                (Target::Module(sel), None) => (Target::Module(identify(sel)), p.x, p.y),
+                (Target::Canvas, _) => (Target::Canvas, p.x, p.y),
+            })
+            .collect();
+
+        for (i, (ta, ax, ay)) in regions.iter().enumerate() {
+            for (j, (tb, bx, by)) in regions.iter().enumerate().skip(i + 1) {
+                if ta != tb {
+                    continue;
+                }
+
+                let sa = self.placements[i].widget.get_shape();
+                let sb = self.placements[j].widget.get_shape();
+                let overlap_x = *ax < bx + sb.x as i32 && *bx < ax + sa.x as i32;
+                let overlap_y = *ay < by + sb.y as i32 && *by < ay + sa.y as i32;
+                if overlap_x && overlap_y {
+                    return Err(ConfigError::Overlap {
+                        first: self.placements[i].name.to_string(),
+                        second: self.placements[j].name.to_string(),
+                        surface: match ta {
+                            Target::Module(sel) => sel.to_string(),
+                            Target::Canvas => "the canvas".to_string(),
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
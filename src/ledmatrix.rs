@@ -1,148 +1,326 @@
 #![allow(dead_code)]
 use crate::matrix;
+use crate::transport::{MatrixTransport, SerialTransport, VirtualHandle, VirtualModule};
+use clap::ValueEnum;
 use serialport::{SerialPortInfo, SerialPortType};
 use std::{
-    thread,
+    fmt, io, thread,
     time::{Duration, SystemTime},
 };
 
-const BRIGHTNESS_CMD: u8 = 0x00;
-const PATTERN_CMD: u8 = 0x01;
-const BOOTLOADER_CMD: u8 = 0x02;
-const SLEEP_CMD: u8 = 0x03;
-const ANIMATE_CMD: u8 = 0x04;
-const PANIC_CMD: u8 = 0x05;
-const DRAW_CMD: u8 = 0x06;
-const SET_COL: u8 = 0x07;
-const COMMIT_COL: u8 = 0x08;
+pub(crate) const BRIGHTNESS_CMD: u8 = 0x00;
+pub(crate) const PATTERN_CMD: u8 = 0x01;
+pub(crate) const BOOTLOADER_CMD: u8 = 0x02;
+pub(crate) const SLEEP_CMD: u8 = 0x03;
+pub(crate) const ANIMATE_CMD: u8 = 0x04;
+pub(crate) const PANIC_CMD: u8 = 0x05;
+pub(crate) const DRAW_CMD: u8 = 0x06;
+pub(crate) const SET_COL: u8 = 0x07;
+pub(crate) const COMMIT_COL: u8 = 0x08;
+pub(crate) const START_GAME_CMD: u8 = 0x10;
+pub(crate) const GAME_CONTROL_CMD: u8 = 0x11;
+pub(crate) const ANIMATION_PERIOD_CMD: u8 = 0x1C;
+pub(crate) const PWM_FREQ_CMD: u8 = 0x1E;
+
+pub(crate) const CHECKFW_CMD: u8 = 0x20;
+
+/// Pattern id that isn't in the Pattern enum, as it takes a parameter
+pub(crate) const PATTERN_PERCENTAGE: u8 = 0x00;
+
+pub(crate) const CMD_START: [u8; 2] = [0x32, 0xAC];
+
+/// Size of every reply sent back by the firmware
+pub(crate) const RESPONSE_SIZE: usize = 32;
+
+/// How long to wait for the firmware to reply
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+///
+/// Patterns built into the firmware
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum Pattern {
+    Gradient = 0x01,
+    DoubleGradient = 0x02,
+    /// "LOTUS" written across the module
+    LotusHorizontal = 0x03,
+    Zigzag = 0x04,
+    /// Every LED fully on
+    FullBrightness = 0x05,
+    /// "PANIC" written down the module
+    Panic = 0x06,
+    /// "LOTUS" written down the module
+    LotusVertical = 0x07,
+}
 
-const CHECKFW_CMD: u8 = 0x20;
+///
+/// Games built into the firmware
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum Game {
+    Snake = 0x00,
+    Pong = 0x01,
+    Tetris = 0x02,
+    /// Conway's game of life
+    Life = 0x03,
+}
 
-const CMD_START: [u8; 2] = [0x32, 0xAC];
+///
+/// What the game of life starts from
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug, Default)]
+pub enum LifeStart {
+    /// Whatever the module is currently showing
+    #[default]
+    CurrentMatrix = 0x00,
+    Pattern1 = 0x01,
+    Blinker = 0x02,
+    Toad = 0x03,
+    Beacon = 0x04,
+    Glider = 0x05,
+    BeaconToadBlinker = 0x06,
+}
+
+///
+/// Keys for controlling a game. Left2 and Right2 move the second player in pong.
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum GameKey {
+    Up = 0x00,
+    Down = 0x01,
+    Left = 0x02,
+    Right = 0x03,
+    Quit = 0x04,
+    Left2 = 0x05,
+    Right2 = 0x06,
+}
+
+///
+/// PWM frequency the LEDs are driven at. Lower frequencies can be quieter
+/// on some modules, but may flicker on camera.
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum PwmFreq {
+    #[value(name = "29khz")]
+    Khz29 = 0x00,
+    #[value(name = "3.6khz")]
+    Khz3_6 = 0x01,
+    #[value(name = "1.8khz")]
+    Khz1_8 = 0x02,
+    #[value(name = "900hz")]
+    Hz900 = 0x03,
+}
+
+impl fmt::Display for PwmFreq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+#[derive(Debug)]
+pub enum LedMatrixError {
+    /// The serial port couldn't be opened (or listed)
+    Open(serialport::Error),
+    /// A command couldn't be written to the module
+    Write(io::Error),
+    /// The module didn't reply in time
+    Timeout,
+    /// The module replied with fewer bytes than expected
+    ShortRead { expected: usize, got: usize },
+    /// The module has been unplugged
+    DeviceGone,
+    /// The module replied with something that doesn't make sense for the command
+    InvalidReply { cmd: u8, reply: u8 },
+}
 
 pub struct LedMatrix {
-    port: Box<dyn serialport::SerialPort>,
+    port: Box<dyn MatrixTransport>,
     pub port_info: SerialPortInfo,
+    /// The last frame sent with draw_matrix, if the module is known to be showing it
+    last_frame: Option<matrix::Matrix>,
 }
 
 impl LedMatrix {
     ///
-    /// Find LED matricies connected to the laptop.
+    /// Find the serial ports of LED matricies connected to the laptop.
     /// Searches for serial ports connected with the LED matrix' product ID & vendor ID
     ///
-    pub fn detect() -> Vec<LedMatrix> {
-        let sports = serialport::available_ports().expect("No ports found!");
+    pub fn find_ports() -> Result<Vec<SerialPortInfo>, LedMatrixError> {
+        let sports = serialport::available_ports().map_err(LedMatrixError::Open)?;
 
         // Loop through all available serial ports, save ports that match the LED matrix product name
         let mut found_ledmat: Vec<SerialPortInfo> = vec![];
         for ref sp in sports {
             // println!("{:?}", sp.port_type);
-            match sp.port_type {
-                SerialPortType::UsbPort(ref info) => {
-                    let info_c = info.clone();
-                    if info_c.vid == 12972 && info_c.pid == 32 {
-                        found_ledmat.push(sp.clone());
-                    }
+            if let SerialPortType::UsbPort(ref info) = sp.port_type {
+                if info.vid == 12972 && info.pid == 32 {
+                    found_ledmat.push(sp.clone());
                 }
-                _ => {}
             }
         }
 
-        if found_ledmat.len() <= 0 {
+        Ok(found_ledmat)
+    }
+
+    ///
+    /// Find and connect to LED matricies connected to the laptop, printing
+    /// each one found along with its firmware version.
+    ///
+    pub fn detect() -> Result<Vec<LedMatrix>, LedMatrixError> {
+        let found_ledmat = Self::find_ports()?;
+
+        if found_ledmat.is_empty() {
             println!("No LED matrix modules found.");
-            return vec![]
+            return Ok(vec![]);
         }
 
+        // A module that fails to open is skipped so the rest can still be used
         let mut mats: Vec<LedMatrix> = Vec::new();
         for m in found_ledmat {
-            mats.push(LedMatrix::new(m));
+            let name = m.port_name.clone();
+            match LedMatrix::new(m) {
+                Ok(mat) => mats.push(mat),
+                Err(e) => println!("{} - {}", name, e),
+            }
         }
 
         println!("Found LED matrix modules:");
-        for i in mats.iter_mut() {
+        for (idx, i) in mats.iter_mut().enumerate() {
+            let version = i
+                .get_fw_version()
+                .unwrap_or_else(|e| format!("Unknown version ({})", e));
             println!(
-                "{} - {}",
-                i.port_info.port_name.to_string(),
-                i.get_fw_version()
+                "{}: {} - {} - Serial: {}",
+                idx,
+                i.port_info.port_name,
+                version,
+                i.serial_number().unwrap_or("none")
             );
         }
 
-        mats
+        Ok(mats)
     }
 
     ///
     /// Creates and connects to an LED matrix
     ///
-    pub fn new(portinfo: SerialPortInfo) -> LedMatrix {
+    pub fn new(portinfo: SerialPortInfo) -> Result<LedMatrix, LedMatrixError> {
         let port0builder = serialport::new(portinfo.port_name.to_string(), 115_200);
-        let port0 = port0builder.open().expect("Failed to open serial port");
+        let port0 = port0builder.open().map_err(LedMatrixError::Open)?;
+
+        Ok(LedMatrix::with_transport(
+            Box::new(SerialTransport::new(port0)),
+            portinfo,
+        ))
+    }
 
+    ///
+    /// Creates an LED matrix that talks over any transport
+    ///
+    pub fn with_transport(port: Box<dyn MatrixTransport>, portinfo: SerialPortInfo) -> LedMatrix {
         LedMatrix {
-            port: port0,
+            port,
             port_info: portinfo,
+            last_frame: None,
         }
     }
 
+    ///
+    /// Creates an in-memory LED matrix with no hardware behind it. The returned
+    /// handle shows what the module would be displaying.
+    ///
+    pub fn new_virtual(name: &str) -> (LedMatrix, VirtualHandle) {
+        let (transport, handle) = VirtualModule::new();
+        let portinfo = SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::Unknown,
+        };
+
+        (LedMatrix::with_transport(Box::new(transport), portinfo), handle)
+    }
+
+    ///
+    /// The USB serial number of the module, if it has one. Unlike the port name,
+    /// this stays the same between boots.
+    ///
+    pub fn serial_number(&self) -> Option<&str> {
+        serial_number(&self.port_info)
+    }
+
     ///
     /// Send a command to the LED matrix module.
     /// 1. Send the bytes 0x32 0xAC to initiate a command
     /// 2. Send the command byte (as listed above)
     /// 3. Send further parameters for the command
     ///
-    pub fn sendcommand(&mut self, cmd: u8, params: Option<&[u8]>) {
+    pub fn sendcommand(&mut self, cmd: u8, params: Option<&[u8]>) -> Result<(), LedMatrixError> {
         let mut buffer: Vec<u8> = vec![];
         buffer.extend_from_slice(CMD_START.as_slice());
         buffer.push(cmd);
-        match params {
-            Some(p) => buffer.extend_from_slice(p),
-            None => {}
-        };
+        if let Some(p) = params {
+            buffer.extend_from_slice(p);
+        }
 
         self.port
-            .write(buffer.as_slice())
-            .expect("Failed to send command");
-        self.port.flush().unwrap();
+            .write_all(buffer.as_slice())
+            .map_err(LedMatrixError::from_write)?;
+        self.port.flush().map_err(LedMatrixError::from_write)
     }
 
     ///
     /// Read back a set amount of bytes from the serial port. Returns Err if
-    /// nothing is read and the port times out
+    /// the port times out before all of them arrive
     ///
     pub fn serialread(
         &mut self,
         numbytes: usize,
         timeout: Duration,
-    ) -> Result<Vec<u8>, &'static str> {
+    ) -> Result<Vec<u8>, LedMatrixError> {
         let start_t = SystemTime::now();
-
-        // Wait for bytes to be available
-        while self.port.bytes_to_read().unwrap() < 1 {
-            if start_t.elapsed().unwrap() > timeout {
-                return Err("Serial read timed out");
-            }
-            thread::sleep(Duration::from_millis(10));
-        }
-
         let mut buffer: Vec<u8> = vec![0; numbytes];
-
-        while self.port.bytes_to_read().unwrap() > 0 {
-            self.port.read(buffer.as_mut_slice()).unwrap();
+        let mut numread = 0;
+
+        while numread < numbytes {
+            let available = self
+                .port
+                .bytes_to_read()
+                .map_err(LedMatrixError::from_read)?;
+
+            if available > 0 {
+                numread += self
+                    .port
+                    .read(&mut buffer[numread..])
+                    .map_err(LedMatrixError::from_read)?;
+            } else if start_t.elapsed().unwrap_or_default() > timeout {
+                return Err(match numread {
+                    0 => LedMatrixError::Timeout,
+                    got => LedMatrixError::ShortRead {
+                        expected: numbytes,
+                        got,
+                    },
+                });
+            } else {
+                thread::sleep(Duration::from_millis(10));
+            }
         }
 
         Ok(buffer)
     }
 
+    ///
+    /// Send a command without parameters, which asks the firmware for the
+    /// current setting, and read back its reply
+    ///
+    fn query(&mut self, cmd: u8) -> Result<Vec<u8>, LedMatrixError> {
+        self.sendcommand(cmd, None)?;
+        self.serialread(RESPONSE_SIZE, RESPONSE_TIMEOUT)
+    }
+
     ///
     /// Get the current firmware version of the LED matrix module.
     ///
-    pub fn get_fw_version(&mut self) -> String {
-        self.sendcommand(CHECKFW_CMD, None);
-        let bytes = self
-            .serialread(32, Duration::from_secs(5))
-            .unwrap_or(vec![0]);
-        if bytes.len() < 3 {
-            return "".to_string();
-        }
+    pub fn get_fw_version(&mut self) -> Result<String, LedMatrixError> {
+        let bytes = self.query(CHECKFW_CMD)?;
 
         let major = bytes[0];
         let minor = (bytes[1] & 0xF0) >> 4;
@@ -151,21 +329,143 @@ impl LedMatrix {
 
         let version = format!("{}.{}.{} Pre Release: {}", major, minor, patch, pre_release);
 
-        version
+        Ok(version)
     }
 
     ///
     /// Tell the module to wake up
     ///
-    pub fn wake(&mut self) {
-        self.sendcommand(SLEEP_CMD, Some(&[0]));
+    pub fn wake(&mut self) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(SLEEP_CMD, Some(&[0]))
     }
 
     ///
     /// Tell the module to go to sleep
     ///
-    pub fn sleep(&mut self) {
-        self.sendcommand(SLEEP_CMD, Some(&[1]));
+    pub fn sleep(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(SLEEP_CMD, Some(&[1]))
+    }
+
+    ///
+    /// Ask the module whether it's asleep
+    ///
+    pub fn is_sleeping(&mut self) -> Result<bool, LedMatrixError> {
+        Ok(self.query(SLEEP_CMD)?[0] != 0)
+    }
+
+    ///
+    /// Start or stop the module scrolling whatever it's showing
+    ///
+    pub fn set_animate(&mut self, animate: bool) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(ANIMATE_CMD, Some(&[animate as u8]))
+    }
+
+    ///
+    /// Stop the module scrolling, e.g. the animation it starts with
+    ///
+    pub fn stop_animation(&mut self) -> Result<(), LedMatrixError> {
+        self.set_animate(false)
+    }
+
+    ///
+    /// Ask the module whether it's scrolling
+    ///
+    pub fn is_animating(&mut self) -> Result<bool, LedMatrixError> {
+        Ok(self.query(ANIMATE_CMD)?[0] != 0)
+    }
+
+    ///
+    /// Set how long the module waits between each step of scrolling or of a game
+    ///
+    pub fn set_animation_period(&mut self, period: Duration) -> Result<(), LedMatrixError> {
+        let ms = period.as_millis().min(u16::MAX as u128) as u16;
+        self.sendcommand(ANIMATION_PERIOD_CMD, Some(&ms.to_le_bytes()))
+    }
+
+    ///
+    /// Ask the module how long it waits between each step of scrolling or of a game
+    ///
+    pub fn get_animation_period(&mut self) -> Result<Duration, LedMatrixError> {
+        let bytes = self.query(ANIMATION_PERIOD_CMD)?;
+        Ok(Duration::from_millis(u16::from_le_bytes([bytes[0], bytes[1]]) as u64))
+    }
+
+    ///
+    /// Set the PWM frequency the LEDs are driven at
+    ///
+    pub fn set_pwm_freq(&mut self, freq: PwmFreq) -> Result<(), LedMatrixError> {
+        self.sendcommand(PWM_FREQ_CMD, Some(&[freq as u8]))
+    }
+
+    ///
+    /// Ask the module which PWM frequency the LEDs are driven at
+    ///
+    pub fn get_pwm_freq(&mut self) -> Result<PwmFreq, LedMatrixError> {
+        let reply = self.query(PWM_FREQ_CMD)?[0];
+        PwmFreq::value_variants()
+            .iter()
+            .find(|f| **f as u8 == reply)
+            .copied()
+            .ok_or(LedMatrixError::InvalidReply {
+                cmd: PWM_FREQ_CMD,
+                reply,
+            })
+    }
+
+    ///
+    /// Draw one of the patterns built into the firmware
+    ///
+    pub fn draw_pattern(&mut self, pattern: Pattern) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(PATTERN_CMD, Some(&[pattern as u8]))
+    }
+
+    ///
+    /// Fill the module from the bottom up to a percentage (0-100) of its height
+    ///
+    pub fn draw_percentage(&mut self, percent: u8) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(PATTERN_CMD, Some(&[PATTERN_PERCENTAGE, percent.min(100)]))
+    }
+
+    ///
+    /// Start one of the games built into the firmware. The start pattern is only
+    /// used by the game of life.
+    ///
+    pub fn start_game(&mut self, game: Game, start: LifeStart) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        match game {
+            Game::Life => self.sendcommand(START_GAME_CMD, Some(&[game as u8, start as u8])),
+            _ => self.sendcommand(START_GAME_CMD, Some(&[game as u8])),
+        }
+    }
+
+    ///
+    /// Press a key in the running game
+    ///
+    pub fn game_control(&mut self, key: GameKey) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(GAME_CONTROL_CMD, Some(&[key as u8]))
+    }
+
+    ///
+    /// Reboot the module into its bootloader, ready for new firmware. The module
+    /// disconnects and comes back as a storage device.
+    ///
+    pub fn reset_to_bootloader(&mut self) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(BOOTLOADER_CMD, None)
+    }
+
+    ///
+    /// Make the firmware crash on purpose, for testing. It shows a panic pattern
+    /// until it's unplugged or the laptop is power cycled.
+    ///
+    pub fn trigger_panic(&mut self) -> Result<(), LedMatrixError> {
+        self.invalidate();
+        self.sendcommand(PANIC_CMD, None)
     }
 
     ///
@@ -176,16 +476,24 @@ impl LedMatrix {
     /// This allows for faster framerates than draw_matrix (with brightnesses) since its
     /// ~0.4% of the data (1/255)
     ///
-    pub fn draw_bool_matrix(&mut self, mat: [[bool; 9]; 34]) {
+    pub fn draw_bool_matrix(&mut self, mat: [[bool; 9]; 34]) -> Result<(), LedMatrixError> {
+        self.invalidate();
         let buffer = matrix::encode(mat);
-        self.sendcommand(DRAW_CMD, Some(buffer.as_slice()));
+        self.sendcommand(DRAW_CMD, Some(buffer.as_slice()))
     }
 
     ///
     /// Sets the brightness of every LED in the module (0=OFF, 255=FULL)
     ///
-    pub fn set_full_brightness(&mut self, val: u8) {
-        self.sendcommand(BRIGHTNESS_CMD, Some(&[val]));
+    pub fn set_full_brightness(&mut self, val: u8) -> Result<(), LedMatrixError> {
+        self.sendcommand(BRIGHTNESS_CMD, Some(&[val]))
+    }
+
+    ///
+    /// Ask the module how bright its LEDs are set to (0=OFF, 255=FULL)
+    ///
+    pub fn get_brightness(&mut self) -> Result<u8, LedMatrixError> {
+        Ok(self.query(BRIGHTNESS_CMD)?[0])
     }
 
     ///
@@ -194,32 +502,119 @@ impl LedMatrix {
     /// Columns are not changed until the commit_col function is run (Allows you to
     /// write all the columns THEN display them at once)
     ///
-    pub fn set_col(&mut self, col: u8, arr: [u8; 34]) {
+    pub fn set_col(&mut self, col: u8, arr: [u8; 34]) -> Result<(), LedMatrixError> {
+        self.invalidate();
         let mut vec = vec![];
         vec.push(col);
         vec.extend_from_slice(arr.as_slice());
-        self.sendcommand(SET_COL, Some(vec.as_slice()));
+        self.sendcommand(SET_COL, Some(vec.as_slice()))
     }
 
     ///
     /// Tell the module to display all the LEDs written to with set_col
     ///
-    pub fn commit_col(&mut self) {
-        self.sendcommand(COMMIT_COL, Some(&[]));
+    pub fn commit_col(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(COMMIT_COL, Some(&[]))
     }
 
     ///
     /// Display an entire matrix with individual LED brightness values. Slow updating,
     /// but allows for more complex UIs
     ///
-    pub fn draw_matrix(&mut self, mat: [[u8; 9]; 34]) {
+    /// Only the columns that differ from the last frame drawn are sent, and nothing
+    /// is sent if the frame hasn't changed. Call invalidate to force a full redraw.
+    ///
+    pub fn draw_matrix(&mut self, mat: [[u8; 9]; 34]) -> Result<(), LedMatrixError> {
         // Transpose array
         let tpose = matrix::transpose(mat);
+        let last = self.last_frame.take().map(matrix::transpose);
 
+        let mut changed = false;
         for i in 0..9 {
-            self.set_col(i, tpose[i as usize]);
+            if last.is_some_and(|l| l[i as usize] == tpose[i as usize]) {
+                continue;
+            }
+            // If this fails, last_frame stays cleared since the module is now
+            // showing something in between
+            self.set_col(i, tpose[i as usize])?;
+            changed = true;
         }
 
-        self.commit_col();
+        if changed {
+            self.commit_col()?;
+        }
+        self.last_frame = Some(mat);
+        Ok(())
+    }
+
+    ///
+    /// Forget the last frame drawn, so the next draw_matrix sends every column
+    ///
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+}
+
+///
+/// The USB serial number of a serial port, if it has one
+///
+pub fn serial_number(info: &SerialPortInfo) -> Option<&str> {
+    match info.port_type {
+        SerialPortType::UsbPort(ref usb) => usb.serial_number.as_deref(),
+        _ => None,
     }
 }
+
+impl LedMatrixError {
+    ///
+    /// Errors that mean the module is no longer there, rather than a one-off failure
+    ///
+    fn is_disconnect(e: &io::Error) -> bool {
+        matches!(
+            e.kind(),
+            io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected | io::ErrorKind::NotFound
+        ) || matches!(e.raw_os_error(), Some(5) | Some(19)) // EIO, ENODEV
+    }
+
+    fn from_write(e: io::Error) -> LedMatrixError {
+        if Self::is_disconnect(&e) {
+            LedMatrixError::DeviceGone
+        } else {
+            LedMatrixError::Write(e)
+        }
+    }
+
+    ///
+    /// Any read failure other than a timeout leaves the port unusable
+    ///
+    fn from_read(e: io::Error) -> LedMatrixError {
+        if e.kind() == io::ErrorKind::TimedOut {
+            LedMatrixError::Timeout
+        } else {
+            LedMatrixError::DeviceGone
+        }
+    }
+}
+
+impl fmt::Display for LedMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedMatrixError::Open(e) => write!(f, "Failed to open serial port: {}", e),
+            LedMatrixError::Write(e) => write!(f, "Failed to send command: {}", e),
+            LedMatrixError::Timeout => write!(f, "Module did not respond in time"),
+            LedMatrixError::ShortRead { expected, got } => write!(
+                f,
+                "Module sent {} bytes, expected {}",
+                got, expected
+            ),
+            LedMatrixError::DeviceGone => write!(f, "Module has been disconnected"),
+            LedMatrixError::InvalidReply { cmd, reply } => write!(
+                f,
+                "Module sent an invalid reply to command {:#04x}: {}",
+                cmd, reply
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LedMatrixError {}
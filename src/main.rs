@@ -1,22 +1,29 @@
+mod config;
+mod control;
+mod daemon;
+mod firmware;
+mod font;
+mod hotplug;
+mod layout;
 mod ledmatrix;
 mod matrix;
+mod metric;
+mod notify;
+mod overlay;
+mod preview;
+mod scheduler;
+mod transport;
 mod widget;
-use std::{
-    env::{args, args_os},
-    process::exit,
-    thread,
-    time::Duration,
-};
-
-use clap::Parser;
-use ledmatrix::LedMatrix;
-use std::time::Instant;
-
-use crate::widget::{AllCPUsWidget, BatteryWidgetUgly, RAMWidget, ClockWidget, UpdatableWidget};
-
-
-const UPDATE_PERIOD:i32 = 500;
+use std::{io::Read, path::PathBuf, process::exit};
 
+use clap::{Parser, Subcommand};
+use config::Config;
+use control::ControlCommand;
+use daemon::Daemon;
+use firmware::FirmwareCommand;
+use hotplug::ModuleManager;
+use ledmatrix::LedMatrix;
+use overlay::ImageFormat;
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
@@ -28,108 +35,360 @@ struct Cli {
 
     /// List all widgets available for placement
     #[arg(long)]
-    list_widgets: bool, // ======== Program Control ========
-                        // #[arg(long)]
-                        // Start the background service updating the matrix
-                        // start: bool,
-
-                        // #[arg(long)]
-                        // JSON config file path
-                        // config: Option<String>,
+    list_widgets: bool,
+
+    /// Name a module in the config file, e.g. "left=0", "right=/dev/ttyACM1" or "left=<serial number>".
+    /// Connected modules are saved by serial number so the name sticks to them between boots.
+    #[arg(long, value_name = "NAME=MODULE", requires = "config")]
+    assign_module: Option<String>,
+
+    // ======== Program Control ========
+    /// JSON config file describing the widget layout. Uses the built-in layout if not given.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Draw on this many virtual modules instead of connected hardware
+    #[arg(long, value_name = "COUNT")]
+    r#virtual: Option<usize>,
+
+    /// Show the layout in the terminal on virtual modules (1 by default, 2 to show them side by side)
+    #[arg(long, value_name = "COUNT", num_args = 0..=1, default_missing_value = "1")]
+    preview: Option<usize>,
+
+    /// Control socket to listen on, or to send commands to with "ctl".
+    /// A named pipe like "\\.\pipe\ledmatrix_widgets" on Windows.
+    #[arg(long, global = true, default_value_t = control::default_endpoint())]
+    socket: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a command to the running instance
+    Ctl {
+        #[command(subcommand)]
+        command: ControlCommand,
+    },
+    /// Send a command straight to a module's firmware. The module can't be in use
+    /// by a running instance at the same time.
+    Module {
+        /// Module to send to, by index, serial number or port name
+        #[arg(long, default_value = "0")]
+        module: String,
+
+        #[command(subcommand)]
+        command: FirmwareCommand,
+    },
 }
 
 enum Program {
     ListMod,
     ListWid,
+    AssignMod(String, String),
+    Ctl(ControlCommand),
+    Firmware(String, FirmwareCommand),
     Default,
 }
 
 fn main() {
-    // TODO possible options:
-    // each widget + Y placement + LED module (both as default) (for now, x maybe later)
-    // Overall brightness
-    // update rate
+    let cli = Cli::parse();
 
     let mut program = Program::Default;
-
-    if args_os().len() > 1 {
-        let cli = Cli::parse();
-        if cli.list_modules {
-            program = Program::ListMod;
-        } else if cli.list_widgets {
-            program = Program::ListWid;
+    if let Some(Command::Ctl { ref command }) = cli.command {
+        program = Program::Ctl(command.clone());
+    } else if let Some(Command::Module { ref module, ref command }) = cli.command {
+        program = Program::Firmware(module.clone(), command.clone());
+    } else if cli.list_modules {
+        program = Program::ListMod;
+    } else if cli.list_widgets {
+        program = Program::ListWid;
+    } else if let Some(ref assign) = cli.assign_module {
+        match assign.split_once('=') {
+            Some((alias, module)) => {
+                program = Program::AssignMod(alias.to_string(), module.to_string())
+            }
+            None => {
+                println!("Expected NAME=MODULE, got \"{}\"", assign);
+                exit(1);
+            }
         }
     }
 
     match program {
         Program::Default => {
-            let mut mats = LedMatrix::detect();
-            if mats.len() == 0 {
-                println!("No modules found, unable to continue.");
-                exit(1);
+            let config = match cli.config {
+                Some(ref path) => Config::load(path).unwrap_or_else(|e| {
+                    println!("{}", e);
+                    exit(1);
+                }),
+                None => Config::default(),
+            };
+
+            let mut previews = vec![];
+            let modules = match cli.preview.or(cli.r#virtual) {
+                Some(count) => {
+                    let mats = (0..count)
+                        .map(|i| {
+                            let (mat, handle) = LedMatrix::new_virtual(&format!("virtual{}", i));
+                            previews.push(handle);
+                            mat
+                        })
+                        .collect();
+                    ModuleManager::with_modules(mats, config.brightness)
+                }
+                None => ModuleManager::new(config.brightness),
+            };
+            if modules.len() == 0 {
+                println!("No modules found, waiting for one to be connected.");
             }
 
-            // No arguments provided? Start the
-            if args().len() <= 1 {
-                let mut bat = BatteryWidgetUgly::new();
-                let mut ram = RAMWidget::new();
-                let mut cpu = AllCPUsWidget::new(false);
-                let mut clock = ClockWidget::new();
+            let layout = config.build_layout(modules.modules()).unwrap_or_else(|e| {
+                println!("{}", e);
+                exit(1);
+            });
 
-                let blank = [[0; 9]; 34];
+            let previews = cli.preview.map(|_| previews);
+            let aliases = config.modules;
+            let mut daemon =
+                Daemon::new(layout, modules, aliases, cli.config, previews, config.notifications);
 
-                if mats.len() == 2 {
-                    mats[1].draw_matrix(blank);
-                }
-                let mut ticker:u8 = 0;
-                loop {
-                    let start = Instant::now();
+            // Carry on without the control socket if it can't be opened, e.g.
+            // when another instance already has it
+            if let Err(e) = daemon.listen(&cli.socket) {
+                println!("Control socket unavailable: {}", e);
+            }
+            daemon.run();
+        }
+        Program::Ctl(command) => {
+            // Paths are sent as given, so make them make sense to the daemon
+            let command = match command {
+                ControlCommand::Layout { config } => ControlCommand::Layout {
+                    config: std::path::absolute(&config).unwrap_or(config),
+                },
+                command => command,
+            };
 
-                    // do stuff
+            // Images are read here and sent after the command
+            let mut payload = vec![];
+            let command = match command {
+                ControlCommand::Frame(mut args) => {
+                    let read = match args.file.to_str() {
+                        Some("-") => std::io::stdin().read_to_end(&mut payload).map(|_| ()),
+                        _ => std::fs::read(&args.file).map(|data| payload = data),
+                    };
+                    if let Err(e) = read {
+                        println!("Unable to read {}: {}", args.file.display(), e);
+                        exit(1);
+                    }
 
-                    bat.update();
-                    ram.update();
-                    if(ticker%2==0)
+                    let extension = args.file.extension().and_then(|e| e.to_str());
+                    args.format = args.format.or(match extension {
+                        Some("pgm") => Some(ImageFormat::Pgm),
+                        Some("json") => Some(ImageFormat::Json),
+                        _ => Some(ImageFormat::Raw),
+                    });
+
+                    // The daemon waits for the whole image, so a short one would
+                    // only fail once it gave up
+                    if args.format == Some(ImageFormat::Raw)
+                        && args.width.checked_mul(args.height) != Some(payload.len())
                     {
-                        cpu.update();
-                        clock.update();
+                        println!(
+                            "{} is {} bytes, but a {}x{} raw image needs one byte per pixel",
+                            args.file.display(),
+                            payload.len(),
+                            args.width,
+                            args.height
+                        );
+                        exit(1);
                     }
-                    ticker = ticker.wrapping_add(1);
-
-                    let mut matrix = [[0; 9]; 34];
-                    matrix = matrix::emplace(matrix, &bat, 0, 0);
-                    matrix = matrix::emplace(matrix, &ram, 0, 3);
-                    matrix = matrix::emplace(matrix, &cpu, 0, 6);
-                    matrix = matrix::emplace(matrix, &clock, 0, 23);
-                    mats[0].draw_matrix(matrix);
-                    let elapsed = start.elapsed().as_millis();
-                    let time_to_sleep = UPDATE_PERIOD-elapsed as i32;
-                    // println!("time: {time_to_sleep}");
-                    if time_to_sleep > 0
-                    {
-                        thread::sleep(Duration::from_millis(time_to_sleep.try_into().unwrap()));
+
+                    // JSON images are sent on a single line, so files spread over
+                    // several lines are packed back into one
+                    if args.format == Some(ImageFormat::Json) {
+                        let rows: Vec<Vec<u8>> =
+                            serde_json::from_slice(&payload).unwrap_or_else(|e| {
+                                println!("Invalid JSON image in {}: {}", args.file.display(), e);
+                                exit(1);
+                            });
+                        payload = serde_json::to_vec(&rows).unwrap();
+                        payload.push(b'\n');
+                    }
+                    ControlCommand::Frame(args)
+                }
+                command => command,
+            };
+
+            match control::send(&cli.socket, &command, &payload) {
+                Ok(info) => {
+                    if !info.is_empty() {
+                        println!("{}", info);
+                    }
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Program::Firmware(module, command) => {
+            // Virtual modules answer like real ones, for trying out scripts
+            let mat = match cli.r#virtual {
+                Some(_) => Ok(LedMatrix::new_virtual("virtual0").0),
+                None => open_module(&module),
+            };
+            let result = mat.and_then(|mut mat| command.run(&mut mat).map_err(|e| e.to_string()));
+            match result {
+                Ok(info) => {
+                    if !info.is_empty() {
+                        println!("{}", info);
                     }
                 }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
             }
         }
         Program::ListMod => {
-            LedMatrix::detect();
+            if let Err(e) = LedMatrix::detect() {
+                println!("{}", e);
+                exit(1);
+            }
+        }
+        Program::AssignMod(alias, module) => {
+            let mats = LedMatrix::detect().unwrap_or_else(|e| {
+                println!("{}", e);
+                exit(1);
+            });
+
+            // Modules that are connected are saved by serial number if they have
+            // one, otherwise the name is saved as given
+            let found = mats.iter().enumerate().find(|(idx, m)| {
+                module == idx.to_string()
+                    || m.serial_number() == Some(module.as_str())
+                    || m.port_info.port_name == module
+            });
+            let target = match found {
+                Some((_, m)) => m
+                    .serial_number()
+                    .unwrap_or(&m.port_info.port_name)
+                    .to_string(),
+                None => module,
+            };
+
+            let path = cli.config.as_ref().unwrap();
+            if let Err(e) = Config::save_alias(path, &alias, &target) {
+                println!("{}", e);
+                exit(1);
+            }
+            println!("Saved \"{}\" as \"{}\" in {}", alias, target, path.display());
         }
         Program::ListWid => {
             println!(
-                "Battery Indicator:\n \
-                A 9x4 widget in the shape of a battery, with an internal bar indicating remaining capacity.\n"
+                "Battery Indicator (\"battery\"):\n \
+                A 9x4 widget in the shape of a battery, with an internal bar indicating remaining capacity.\n \
+                All batteries are added together, and the outline is dotted when there is no battery.\n \
+                Options: \"show_time\" (row of hours until empty or full), \"show_rate\" (row of 10W per pixel)\n"
+            );
+            println!(
+                "Battery Bar (\"battery_ugly\"):\n \
+                A 9x2 bar indicating remaining capacity, animated while charging or discharging.\n \
+                All batteries are added together, and the bar is dotted when there is no battery.\n \
+                Options: \"show_time\" (row of hours until empty or full), \"show_rate\" (row of 10W per pixel)\n"
+            );
+            println!(
+                "RAM Usage Indicator (\"ram\"):\n \
+                A 9x2 bar indicating the fraction of memory in use.\n"
+            );
+            println!(
+                "CPU Usage Indicator (\"cpu\"):\n \
+                A 9xN bar indicating the overall CPU usage.\n \
+                Options: \"bar_height\" (rows)\n"
             );
             println!(
-                "CPU Usage Indicator:\n \
-                A 9x16 widget where each row of LEDs is a bar that represents the CPU usage of one core.\n"
+                "Per-Core CPU Usage Indicator (\"all_cpus\"):\n \
+                A 9xN widget where each row of LEDs is a bar that represents the CPU usage of one core.\n \
+                With \"merge_threads\", each column is a vertical bar for a pair of threads instead.\n \
+                CPUs are averaged together if there are more than fit in the widget.\n \
+                Options: \"merge_threads\" (bool), \"width\", \"height\"\n"
             );
             println!(
-                "Clock Widget:\n \
-                A 9x11 widget that displays the system time in 24hr format.\n"
+                "Clock Widget (\"clock\"):\n \
+                A 9xN widget that displays the time (hours above minutes) or date in the system's or a given timezone.\n \
+                In digits it is 9x11, or 9x17 with seconds digits; 9x3 or 9x5 in binary; 9x4 in BCD. A seconds bar adds 2 rows.\n \
+                Options: \"format\" (\"24h\" or \"12h\" with an AM/PM dot), \"seconds\" (\"none\", \"bar\" or \"digits\"), \
+                \"view\" (\"time\" or \"date\" for day above month), \"style\" (\"digits\", \"binary\" or \"bcd\"), \
+                \"timezone\" (e.g. \"Europe/Berlin\", local time if not given)\n"
+            );
+            println!(
+                "Disk Usage Indicator (\"disk\"):\n \
+                A 9xN widget with a bar per disk showing how full it is. Disks that aren't mounted are dotted.\n \
+                Options: \"disks\" (list of mount points or device names, the disks mounted at startup if empty), \"bar_height\" (rows per disk)\n"
+            );
+            println!(
+                "Network Traffic Indicator (\"network\"):\n \
+                Receive and transmit rates, either as two 9xN bars or a 9xN scrolling graph.\n \
+                Options: \"interface\" (all but loopback if not given), \"style\" (\"bars\" or \"graph\"), \
+                \"scale\" (\"log\" or \"auto\"), \"height\" (rows per bar, or graph height)\n"
+            );
+            println!(
+                "Load Average Indicator (\"load\"):\n \
+                Three 9xN bars for the 1, 5 and 15 minute load averages, full at one task per CPU.\n \
+                Options: \"bar_height\" (rows per bar), \"count\" (\"none\", \"processes\" or \"threads\", shown as hundreds above units)\n"
+            );
+            println!(
+                "Temperature Indicator (\"temperature\"):\n \
+                A 9xN widget with a bar per sensor between a minimum and maximum temperature, blinking or brightening \
+                above the critical temperature, optionally followed by a dimmer bar per fan.\n \
+                Options: \"sensors\" (list of labels, hottest sensor if empty), \"min\", \"max\", \"critical\" (degrees C), \
+                \"critical_style\" (\"blink\" or \"brighten\"), \"fans\" (bool), \"fan_max\" (RPM), \"bar_height\"\n"
+            );
+            println!(
+                "Scrolling Text (\"text\"):\n \
+                A line of text scrolling through the widget, or drawn still if it fits.\n \
+                Options: \"source\" ({{\"text\": \"...\"}}, {{\"file\": \"path\"}} re-read every second, or \"stdin\"), \
+                \"direction\" (\"horizontal\", or \"vertical\" with rotated letters), \"font\" (\"3x5\" or \"5x7\"), \
+                \"spacing\", \"length\", \"gap\", \"speed\" (pixels per second)\n"
+            );
+            println!(
+                "History Graph (\"history\"):\n \
+                A WxH scrolling graph of a metric, one column per update with the newest on the right.\n \
+                Options: \"metric\" (\"cpu\", \"ram\", \"network\", \"network_rx\", \"network_tx\" or \"temperature\"), \
+                \"interface\" (for network metrics), \"width\" (default 9), \"height\" (default 8)\n"
+            );
+            println!(
+                "Command Output (\"command\"):\n \
+                Runs a program on every update and draws what it prints as a bar, text or pixels.\n \
+                Programs that fail or time out are drawn as diagonal stripes, with the error printed here.\n \
+                Options: \"command\" (shell command line, or list of program and arguments), \
+                \"output\" (\"number\", \"text\" or \"pixels\" with a row of 0-255 per line), \"min\", \"max\" (default 100), \
+                \"timeout\" (ms, default 2000), \"font\", \"spacing\", \"width\", \"height\"\n"
             );
         } // _ => {}
     }
 
     exit(0);
 }
+
+///
+/// Connect to a single module by its index in the detected list, its USB serial
+/// number or its port name
+///
+fn open_module(module: &str) -> Result<LedMatrix, String> {
+    let ports = LedMatrix::find_ports().map_err(|e| e.to_string())?;
+    let port = ports
+        .iter()
+        .enumerate()
+        .find(|(idx, p)| {
+            module == idx.to_string()
+                || ledmatrix::serial_number(p) == Some(module)
+                || p.port_name == module
+        })
+        .map(|(_, p)| p.clone())
+        .ok_or_else(|| format!("No module \"{}\" found", module))?;
+
+    LedMatrix::new(port).map_err(|e| e.to_string())
+}
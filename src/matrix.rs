@@ -1,4 +1,5 @@
 use crate::widget::UpdatableWidget;
+use std::fmt;
 
 pub(crate) type Matrix = [[u8;9]; 34];
 
@@ -10,10 +11,10 @@ pub fn encode(arr: [[bool; 9]; 34]) -> [u8; 39] {
     let mut out_arr: [u8; 39] = [0; 39];
     let mut index = 0;
     let mut byte_offs = 0;
-    for i in 0..34 {
-        for j in 0..9 {
-            let newval = if arr[i][j] { 0x01 } else { 0x00 };
-            out_arr[index] = out_arr[index] | (newval << byte_offs);
+    for row in arr.iter() {
+        for &px in row.iter() {
+            let newval = if px { 0x01 } else { 0x00 };
+            out_arr[index] |= newval << byte_offs;
 
             index = if byte_offs >= 7 { index + 1 } else { index };
             byte_offs = if byte_offs >= 7 { 0 } else { byte_offs + 1 };
@@ -39,17 +40,195 @@ pub fn transpose(arr: Matrix) -> [[u8;34]; 9] {
 }
 
 ///
-/// Overlay a smaller matrix on a larger matrix with a given position
-/// 
-pub fn emplace(orig: Matrix, widget: &impl UpdatableWidget, x: usize, y: usize) -> Matrix {
-    // assert!(x as usize + widget.width < 9 && y as usize + widget.height < 34);
-    let mut out: [[u8; 9]; 34] = orig.clone();
+/// A drawing surface of any size, e.g. a single module or several modules
+/// put together. Pixels are stored row by row, like widget matrices.
+///
+#[derive(Clone)]
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+///
+/// Part of a widget fell outside the canvas and was clipped off
+///
+#[derive(Debug)]
+pub struct Overflow {
+    /// Pixels that weren't drawn
+    pub hidden: usize,
+    /// Every pixel in the widget
+    pub total: usize,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} pixels are off the edge", self.hidden, self.total)
+    }
+}
 
-    for i in 0..widget.get_shape().y {
-        for j in 0..widget.get_shape().x {
-            out[i + y][j + x] = widget.get_matrix()[j + (widget.get_shape().x * i)];
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0; width * height],
         }
     }
 
-    out
+    ///
+    /// Overlay a widget on the canvas with a given position. The position can be
+    /// negative or past the edge, in which case only the part of the widget that
+    /// lands on the canvas is drawn, and the rest is reported as an Overflow.
+    ///
+    pub fn emplace(
+        &mut self,
+        widget: &(impl UpdatableWidget + ?Sized),
+        x: i32,
+        y: i32,
+    ) -> Result<(), Overflow> {
+        let shape = widget.get_shape();
+        let pixels = widget.get_matrix();
+        let mut hidden = 0;
+
+        for i in 0..shape.y {
+            for j in 0..shape.x {
+                let cy = y as isize + i as isize;
+                let cx = x as isize + j as isize;
+                if cy < 0 || cx < 0 || cy as usize >= self.height || cx as usize >= self.width {
+                    hidden += 1;
+                    continue;
+                }
+
+                // Widgets that draw fewer pixels than their shape are padded with OFF
+                let px = pixels.get(j + (shape.x * i)).copied().unwrap_or(0);
+                self.pixels[cy as usize * self.width + cx as usize] = px;
+            }
+        }
+
+        match hidden {
+            0 => Ok(()),
+            _ => Err(Overflow {
+                hidden,
+                total: shape.x * shape.y,
+            }),
+        }
+    }
+
+    ///
+    /// Copy out a rectangular section of the canvas
+    ///
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+        let mut out = Canvas::new(width, height);
+        for i in 0..height {
+            let start = (i + y) * self.width + x;
+            out.pixels[i * width..(i + 1) * width].copy_from_slice(&self.pixels[start..start + width]);
+        }
+        out
+    }
+
+    ///
+    /// Convert a module sized (9x34) canvas to a matrix that can be drawn
+    ///
+    pub fn to_matrix(&self) -> Matrix {
+        assert!(self.width == 9 && self.height == 34);
+        let mut out: Matrix = [[0; 9]; 34];
+        for (i, row) in out.iter_mut().enumerate() {
+            row.copy_from_slice(&self.pixels[i * 9..(i + 1) * 9]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay::Image;
+
+    /// A widget whose pixels count up from 1, so each can be told apart
+    fn numbered(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: (1..=(width * height) as u8).collect(),
+        }
+    }
+
+    fn pixel(canvas: &Canvas, x: usize, y: usize) -> u8 {
+        canvas.pixels[y * canvas.width + x]
+    }
+
+    #[test]
+    fn emplace_inside() {
+        let mut canvas = Canvas::new(9, 34);
+        assert!(canvas.emplace(&numbered(3, 2), 2, 5).is_ok());
+        assert_eq!(pixel(&canvas, 2, 5), 1);
+        assert_eq!(pixel(&canvas, 4, 5), 3);
+        assert_eq!(pixel(&canvas, 2, 6), 4);
+        assert_eq!(pixel(&canvas, 4, 6), 6);
+        assert_eq!(canvas.pixels.iter().filter(|&&px| px != 0).count(), 6);
+    }
+
+    #[test]
+    fn emplace_negative_offset() {
+        let mut canvas = Canvas::new(9, 34);
+        let overflow = canvas.emplace(&numbered(3, 3), -1, -2).unwrap_err();
+        // Only the bottom row's last two pixels land on the canvas
+        assert_eq!((overflow.hidden, overflow.total), (7, 9));
+        assert_eq!(pixel(&canvas, 0, 0), 8);
+        assert_eq!(pixel(&canvas, 1, 0), 9);
+        assert_eq!(pixel(&canvas, 0, 1), 0);
+    }
+
+    #[test]
+    fn emplace_past_edge() {
+        let mut canvas = Canvas::new(9, 34);
+        let overflow = canvas.emplace(&numbered(3, 2), 8, 33).unwrap_err();
+        assert_eq!((overflow.hidden, overflow.total), (5, 6));
+        assert_eq!(pixel(&canvas, 8, 33), 1);
+        assert_eq!(overflow.to_string(), "5 of 6 pixels are off the edge");
+
+        let overflow = canvas.emplace(&numbered(2, 2), 20, -10).unwrap_err();
+        assert_eq!((overflow.hidden, overflow.total), (4, 4));
+    }
+
+    #[test]
+    fn emplace_pads_short_widget() {
+        let mut canvas = Canvas::new(9, 34);
+        canvas.emplace(&numbered(2, 2), 0, 0).unwrap();
+        let short = Image {
+            width: 2,
+            height: 2,
+            pixels: vec![7],
+        };
+        canvas.emplace(&short, 0, 0).unwrap();
+        assert_eq!([pixel(&canvas, 0, 0), pixel(&canvas, 1, 0), pixel(&canvas, 1, 1)], [7, 0, 0]);
+    }
+
+    #[test]
+    fn crop_side_by_side() {
+        let mut canvas = Canvas::new(18, 34);
+        canvas.emplace(&numbered(2, 1), 8, 0).unwrap();
+        canvas.emplace(&numbered(1, 1), 17, 33).unwrap();
+
+        let left = canvas.crop(0, 0, 9, 34).to_matrix();
+        let right = canvas.crop(9, 0, 9, 34).to_matrix();
+        assert_eq!(left[0][8], 1);
+        assert_eq!(right[0][0], 2);
+        assert_eq!(right[33][8], 1);
+        assert_eq!(left.iter().flatten().filter(|&&px| px != 0).count(), 1);
+        assert_eq!(right.iter().flatten().filter(|&&px| px != 0).count(), 2);
+    }
+
+    #[test]
+    fn crop_stacked() {
+        let mut canvas = Canvas::new(9, 68);
+        canvas.emplace(&numbered(1, 2), 4, 33).unwrap();
+
+        let top = canvas.crop(0, 0, 9, 34).to_matrix();
+        let bottom = canvas.crop(0, 34, 9, 34).to_matrix();
+        assert_eq!(top[33][4], 1);
+        assert_eq!(bottom[0][4], 2);
+        assert_eq!(bottom.iter().flatten().filter(|&&px| px != 0).count(), 1);
+    }
 }
@@ -0,0 +1,170 @@
+use serde::Deserialize;
+use std::time::Instant;
+
+/// Slowest rate shown on the logarithmic network scale, in bytes per second
+const NET_LOG_MIN: f32 = 100.0;
+/// Fastest rate shown on the logarithmic network scale, in bytes per second (1 Gbit/s)
+const NET_LOG_MAX: f32 = 125_000_000.0;
+/// Temperature shown as an empty graph, in degrees C
+const TEMP_MIN: f32 = 30.0;
+/// Temperature shown as a full graph if the sensor doesn't report a critical
+/// temperature, in degrees C
+const TEMP_MAX: f32 = 100.0;
+
+///
+/// A numeric value that can be sampled from the system, e.g. for a history graph.
+/// Selected with the "metric" key, e.g. `{"type": "history", "metric": "ram"}`
+///
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Overall CPU usage
+    Cpu,
+    /// Used memory
+    Ram,
+    /// Receive and transmit rates added together, on a logarithmic scale
+    Network,
+    /// Receive rate, on a logarithmic scale
+    NetworkRx,
+    /// Transmit rate, on a logarithmic scale
+    NetworkTx,
+    /// The hottest temperature sensor, from 30C up to its critical temperature
+    Temperature,
+}
+
+///
+/// Whether a network interface is loopback, which isn't worth showing
+///
+fn is_loopback(name: &str) -> bool {
+    name == "lo" || name.starts_with("lo0") || name.starts_with("Loopback")
+}
+
+///
+/// Convert a network rate (in bytes per second) to a fraction of 1 Gbit/s on a
+/// logarithmic scale, so idle and busy links are both readable
+///
+pub fn network_log_scale(rate: f32) -> f32 {
+    if rate <= NET_LOG_MIN {
+        return 0.0;
+    }
+    ((rate / NET_LOG_MIN).log10() / (NET_LOG_MAX / NET_LOG_MIN).log10()).min(1.0)
+}
+
+///
+/// Measures how fast an interface, or every non-loopback interface, is receiving
+/// and transmitting
+///
+pub struct NetworkSampler {
+    /// The interface to sample, or every non-loopback interface if None
+    interface: Option<String>,
+    networks: sysinfo::Networks,
+    last_sample: Instant,
+}
+
+impl NetworkSampler {
+    pub fn new(interface: Option<String>) -> NetworkSampler {
+        // Rates are measured since the last refresh, so refresh once up front
+        NetworkSampler {
+            interface,
+            networks: sysinfo::Networks::new_with_refreshed_list(),
+            last_sample: Instant::now(),
+        }
+    }
+
+    ///
+    /// (receive, transmit) rates in bytes per second since the last sample
+    ///
+    pub fn sample(&mut self) -> (f32, f32) {
+        // Refresh the whole list, so interfaces that come up are picked up
+        self.networks.refresh_list();
+        let elapsed = self.last_sample.elapsed().as_secs_f32().max(0.001);
+        self.last_sample = Instant::now();
+
+        let (mut rx, mut tx) = (0, 0);
+        for (name, data) in self.networks.iter() {
+            let selected = match self.interface {
+                Some(ref iface) => name == iface,
+                None => !is_loopback(name),
+            };
+            if selected {
+                rx += data.received();
+                tx += data.transmitted();
+            }
+        }
+        (rx as f32 / elapsed, tx as f32 / elapsed)
+    }
+}
+
+///
+/// Everything needed to keep sampling a metric
+///
+pub struct MetricSource {
+    metric: Metric,
+    sys: sysinfo::System,
+    /// Only set for the network metrics
+    network: Option<NetworkSampler>,
+    components: sysinfo::Components,
+}
+
+impl MetricSource {
+    pub fn new(metric: Metric, interface: Option<String>) -> MetricSource {
+        let network_metric = matches!(
+            metric,
+            Metric::Network | Metric::NetworkRx | Metric::NetworkTx
+        );
+        let mut source = MetricSource {
+            metric,
+            sys: sysinfo::System::new(),
+            network: network_metric.then(|| NetworkSampler::new(interface)),
+            components: sysinfo::Components::new(),
+        };
+
+        // Only load what's needed. CPU usage is measured since the last refresh,
+        // so it's refreshed once up front too.
+        match metric {
+            Metric::Cpu => source.sys.refresh_cpu(),
+            Metric::Temperature => source.components.refresh_list(),
+            _ => {}
+        }
+        source
+    }
+
+    ///
+    /// Take a new sample, as a fraction of the metric's full scale (0.0 - 1.0)
+    ///
+    pub fn sample(&mut self) -> f32 {
+        let value = match self.metric {
+            Metric::Cpu => {
+                self.sys.refresh_cpu();
+                self.sys.global_cpu_info().cpu_usage() / 100.0
+            }
+            Metric::Ram => {
+                self.sys.refresh_memory();
+                self.sys.used_memory() as f32 / self.sys.total_memory().max(1) as f32
+            }
+            Metric::Network | Metric::NetworkRx | Metric::NetworkTx => {
+                let (rx, tx) = self
+                    .network
+                    .as_mut()
+                    .map_or((0.0, 0.0), NetworkSampler::sample);
+                network_log_scale(match self.metric {
+                    Metric::NetworkRx => rx,
+                    Metric::NetworkTx => tx,
+                    _ => rx + tx,
+                })
+            }
+            Metric::Temperature => {
+                self.components.refresh();
+                self.components
+                    .iter()
+                    .map(|c| {
+                        let max = c.critical().filter(|t| *t > TEMP_MIN).unwrap_or(TEMP_MAX);
+                        (c.temperature() - TEMP_MIN) / (max - TEMP_MIN)
+                    })
+                    .fold(0.0, f32::max)
+            }
+        };
+
+        value.clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,322 @@
+use crate::config::{ModuleSelector, MODULE_HEIGHT, MODULE_WIDTH};
+use crate::control::ControlRequest;
+use crate::overlay::Image;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{fmt, str::FromStr, sync::mpsc::Sender};
+
+///
+/// How urgent a notification is, as given by the app that sent it
+///
+#[derive(Deserialize, ValueEnum, Clone, Copy, Default, PartialEq, PartialOrd, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Urgency::Low => write!(f, "low"),
+            Urgency::Normal => write!(f, "normal"),
+            Urgency::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+impl FromStr for Urgency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Urgency, String> {
+        <Urgency as ValueEnum>::from_str(s, true)
+    }
+}
+
+///
+/// What's drawn over the widgets when a notification arrives
+///
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationAnimation {
+    /// The whole module blinks
+    #[default]
+    Flash,
+    /// The whole module fades in and out
+    Pulse,
+    /// A bell blinks in the middle of the module
+    Icon,
+}
+
+///
+/// The "notifications" section of the config file. Notifications are only
+/// watched for if it's there.
+///
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    #[serde(default)]
+    pub animation: NotificationAnimation,
+
+    /// Modules to play the animation on. Every module if empty.
+    #[serde(default)]
+    pub modules: Vec<ModuleSelector>,
+
+    /// How long the animation plays for, in milliseconds
+    #[serde(default = "default_duration")]
+    pub duration: u64,
+
+    /// How long each frame of the animation is shown, in milliseconds
+    #[serde(default = "default_frame_period")]
+    pub frame_period: u64,
+
+    /// Brightness of the animation (0-255)
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+
+    /// Animations are drawn over images from other programs with a lower priority
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Only react to these apps, e.g. "Thunderbird". Every app if empty.
+    #[serde(default)]
+    pub apps: Vec<String>,
+
+    /// Never react to these apps
+    #[serde(default)]
+    pub ignore_apps: Vec<String>,
+
+    /// Ignore notifications less urgent than this
+    #[serde(default = "default_min_urgency")]
+    pub urgency: Urgency,
+}
+
+fn default_duration() -> u64 {
+    2000
+}
+
+fn default_frame_period() -> u64 {
+    250
+}
+
+fn default_brightness() -> u8 {
+    255
+}
+
+fn default_min_urgency() -> Urgency {
+    Urgency::Low
+}
+
+impl NotificationConfig {
+    ///
+    /// Whether a notification from an app should play the animation. App names
+    /// are compared ignoring case.
+    ///
+    pub fn accepts(&self, app: &str, urgency: Urgency) -> bool {
+        let listed = |apps: &[String]| apps.iter().any(|a| a.eq_ignore_ascii_case(app));
+        urgency >= self.urgency
+            && (self.apps.is_empty() || listed(&self.apps))
+            && !listed(&self.ignore_apps)
+    }
+
+    ///
+    /// Where the animation goes on a module, and its frames
+    ///
+    pub fn animation(&self) -> (i32, i32, Vec<Option<Image>>) {
+        let fill = |brightness| Image {
+            width: MODULE_WIDTH,
+            height: MODULE_HEIGHT,
+            pixels: vec![brightness; MODULE_WIDTH * MODULE_HEIGHT],
+        };
+        let b = self.brightness as u16;
+
+        match self.animation {
+            NotificationAnimation::Flash => (0, 0, vec![Some(fill(self.brightness)), None]),
+            NotificationAnimation::Pulse => {
+                let frames = [1, 2, 3, 4, 3, 2, 1].map(|step| Some(fill((b * step / 4) as u8)));
+                (0, 0, frames.into_iter().chain([None]).collect())
+            }
+            NotificationAnimation::Icon => {
+                // Drawn on a dark square so it stands out from the widgets
+                let pixels = BELL
+                    .iter()
+                    .flat_map(|row| row.chars())
+                    .map(|px| match px {
+                        '#' => self.brightness,
+                        _ => 0,
+                    })
+                    .collect();
+                let icon = Image {
+                    width: BELL[0].len(),
+                    height: BELL.len(),
+                    pixels,
+                };
+                let y = (MODULE_HEIGHT - icon.height) as i32 / 2;
+                (0, y, vec![Some(icon), None])
+            }
+        }
+    }
+}
+
+#[rustfmt::skip]
+const BELL: [&str; 9] = [
+    ".........",
+    "....#....",
+    "...###...",
+    "..#####..",
+    "..#####..",
+    "..#####..",
+    ".#######.",
+    "....#....",
+    ".........",
+];
+
+///
+/// Watch the session bus for notifications in the background, sending each one
+/// to the main loop as a notify command. The notification server is left to do
+/// its job, this only looks at the messages sent to it.
+///
+#[cfg(feature = "notifications")]
+pub fn watch(requests: Sender<ControlRequest>) {
+    std::thread::spawn(move || {
+        let watched = zbus::blocking::Connection::session().and_then(|c| watch_bus(&c, &requests));
+        if let Err(e) = watched {
+            println!("Unable to watch for notifications: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "notifications"))]
+pub fn watch(_requests: Sender<ControlRequest>) {
+    println!("Unable to watch for notifications: built without the \"notifications\" feature");
+}
+
+///
+/// Pass on every notification sent over a bus until the main loop goes away.
+/// The connection is used up, since a monitor can't do anything else.
+///
+#[cfg(feature = "notifications")]
+pub(crate) fn watch_bus(
+    connection: &zbus::blocking::Connection,
+    requests: &Sender<ControlRequest>,
+) -> zbus::Result<()> {
+    use crate::control::ControlCommand;
+    use std::collections::HashMap;
+    use zbus::{
+        blocking::{fdo::MonitoringProxy, MessageIterator},
+        message::Type,
+        zvariant::OwnedValue,
+        MatchRule,
+    };
+
+    // Set up before becoming a monitor, so nothing is missed in between
+    let messages = MessageIterator::from(connection);
+    let rule = MatchRule::builder()
+        .msg_type(Type::MethodCall)
+        .interface("org.freedesktop.Notifications")?
+        .member("Notify")?
+        .build();
+    MonitoringProxy::new(connection)?.become_monitor(&[rule], 0)?;
+    println!("Watching for notifications");
+
+    for message in messages {
+        let message = message?;
+        if message.message_type() != Type::MethodCall
+            || message.header().member().is_none_or(|m| m.as_str() != "Notify")
+        {
+            continue;
+        }
+
+        // app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout
+        type Notify = (String, u32, String, String, String, Vec<String>, HashMap<String, OwnedValue>, i32);
+        let Ok((app, _, _, _, _, _, hints, _)) = message.body().deserialize::<Notify>() else {
+            continue;
+        };
+        let urgency = match hints.get("urgency").and_then(|u| u.downcast_ref::<u8>().ok()) {
+            Some(0) => Urgency::Low,
+            Some(2) => Urgency::Critical,
+            _ => Urgency::Normal,
+        };
+
+        // Nobody waits for the reply
+        let (request, _) = ControlRequest::new(ControlCommand::Notify { urgency, app });
+        if requests.send(request).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(animation: NotificationAnimation) -> NotificationConfig {
+        let mut config: NotificationConfig = serde_json::from_str("{}").unwrap();
+        config.animation = animation;
+        config.brightness = 200;
+        config
+    }
+
+    /// Brightness of every pixel in a frame, or None for a gap
+    fn levels(frames: &[Option<Image>]) -> Vec<Option<Vec<u8>>> {
+        frames.iter().map(|f| f.as_ref().map(|img| img.pixels.clone())).collect()
+    }
+
+    #[test]
+    fn flash_blinks_whole_module() {
+        let (x, y, frames) = config(NotificationAnimation::Flash).animation();
+        assert_eq!((x, y), (0, 0));
+        assert_eq!(levels(&frames), [Some(vec![200; MODULE_WIDTH * MODULE_HEIGHT]), None]);
+    }
+
+    #[test]
+    fn pulse_fades_in_and_out() {
+        let (x, y, frames) = config(NotificationAnimation::Pulse).animation();
+        assert_eq!((x, y), (0, 0));
+        assert_eq!(frames.len(), 8);
+        assert!(frames[7].is_none());
+
+        let steps: Vec<u8> = frames[..7]
+            .iter()
+            .map(|f| {
+                let img = f.as_ref().unwrap();
+                assert_eq!((img.width, img.height), (MODULE_WIDTH, MODULE_HEIGHT));
+                assert!(img.pixels.iter().all(|&px| px == img.pixels[0]));
+                img.pixels[0]
+            })
+            .collect();
+        assert_eq!(steps, [50, 100, 150, 200, 150, 100, 50]);
+    }
+
+    #[test]
+    fn icon_centred_on_module() {
+        let (x, y, frames) = config(NotificationAnimation::Icon).animation();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[1].is_none());
+
+        let icon = frames[0].as_ref().unwrap();
+        assert_eq!((icon.width, icon.height), (9, 9));
+        assert_eq!((x, y), (0, 12));
+        // Dark border, lit clapper
+        assert!(icon.pixels[..9].iter().all(|&px| px == 0));
+        assert_eq!(icon.pixels[7 * 9 + 4], 200);
+    }
+
+    #[test]
+    fn filters() {
+        let mut config = config(NotificationAnimation::Flash);
+        config.urgency = Urgency::Normal;
+        config.ignore_apps = vec!["Spotify".to_string()];
+        assert!(config.accepts("Thunderbird", Urgency::Normal));
+        assert!(!config.accepts("Thunderbird", Urgency::Low));
+        assert!(!config.accepts("spotify", Urgency::Critical));
+
+        config.apps = vec!["thunderbird".to_string()];
+        assert!(config.accepts("Thunderbird", Urgency::Critical));
+        assert!(!config.accepts("Firefox", Urgency::Critical));
+    }
+}
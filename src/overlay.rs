@@ -0,0 +1,495 @@
+use crate::config::{ModuleSelector, MODULE_HEIGHT, MODULE_WIDTH};
+use crate::hotplug::Module;
+use crate::matrix::Canvas;
+use crate::widget::{Shape, UpdatableWidget};
+use clap::ValueEnum;
+use std::{
+    fmt,
+    io::{self, BufRead, Read},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Largest image accepted from other programs, enough to cover a few modules
+const MAX_IMAGE_PIXELS: usize = MODULE_WIDTH * MODULE_HEIGHT * 4;
+/// Longest side of an image, enough for a few modules side by side or stacked
+const MAX_IMAGE_SIDE: usize = MODULE_HEIGHT * 4;
+
+///
+/// How an image pushed by another program is encoded
+///
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum ImageFormat {
+    /// One byte per pixel, row by row. The size must be given with width and height.
+    Raw,
+    /// A single line holding an array of rows, e.g. `[[0, 255], [255, 0]]`
+    Json,
+    /// A portable greymap (P5 binary or P2 plain text), scaled to 0-255
+    Pgm,
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFormat::Raw => write!(f, "raw"),
+            ImageFormat::Json => write!(f, "json"),
+            ImageFormat::Pgm => write!(f, "pgm"),
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageFormat, String> {
+        <ImageFormat as ValueEnum>::from_str(s, true)
+    }
+}
+
+///
+/// A greyscale image, row by row
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    ///
+    /// Read an image from a stream. The width and height are only used for raw
+    /// images, every other format says how big it is.
+    ///
+    pub fn read(
+        reader: &mut impl BufRead,
+        format: ImageFormat,
+        width: usize,
+        height: usize,
+    ) -> io::Result<Image> {
+        let image = match format {
+            ImageFormat::Raw => {
+                check_size(width, height)?;
+                let mut pixels = vec![0; width * height];
+                reader.read_exact(&mut pixels)?;
+                Image {
+                    width,
+                    height,
+                    pixels,
+                }
+            }
+            ImageFormat::Json => {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let rows: Vec<Vec<u8>> = serde_json::from_str(&line)
+                    .map_err(|e| invalid(format!("Invalid JSON image: {}", e)))?;
+                let width = rows.first().map(|r| r.len()).unwrap_or(0);
+                if rows.iter().any(|r| r.len() != width) {
+                    return Err(invalid("Every row of a JSON image must be the same length"));
+                }
+                check_size(width, rows.len())?;
+                Image {
+                    width,
+                    height: rows.len(),
+                    pixels: rows.concat(),
+                }
+            }
+            ImageFormat::Pgm => read_pgm(reader)?,
+        };
+
+        Ok(image)
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+///
+/// Check an image's size before anything is allocated for it. Each side is
+/// capped first, so the sizes can't overflow when multiplied.
+///
+fn check_size(width: usize, height: usize) -> io::Result<()> {
+    let too_big = || {
+        invalid(format!(
+            "Images can be at most {} pixels across and {} pixels in total, got {}x{}",
+            MAX_IMAGE_SIDE, MAX_IMAGE_PIXELS, width, height
+        ))
+    };
+    if width > MAX_IMAGE_SIDE || height > MAX_IMAGE_SIDE {
+        return Err(too_big());
+    }
+
+    match width.checked_mul(height) {
+        Some(0) => Err(invalid("Images can't be empty")),
+        Some(n) if n <= MAX_IMAGE_PIXELS => Ok(()),
+        _ => Err(too_big()),
+    }
+}
+
+///
+/// Read the next whitespace separated token of a PGM header, skipping comments.
+/// The single whitespace byte after the token is consumed too, as the format
+/// requires before binary data.
+///
+fn pgm_token(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut token = String::new();
+    let mut comment = false;
+    for byte in reader.bytes() {
+        let byte = byte?;
+        match byte {
+            b'\n' | b'\r' => comment = false,
+            _ if comment => {}
+            b'#' if token.is_empty() => comment = true,
+            b if b.is_ascii_whitespace() => {}
+            b => {
+                token.push(b as char);
+                continue;
+            }
+        }
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    match token.is_empty() {
+        true => Err(io::ErrorKind::UnexpectedEof.into()),
+        false => Ok(token),
+    }
+}
+
+fn read_pgm(reader: &mut impl BufRead) -> io::Result<Image> {
+    let number = |reader: &mut _| -> io::Result<usize> {
+        let token = pgm_token(reader)?;
+        token
+            .parse()
+            .map_err(|_| invalid(format!("Expected a number in the PGM image, got \"{}\"", token)))
+    };
+
+    let magic = pgm_token(reader)?;
+    let width = number(reader)?;
+    let height = number(reader)?;
+    let maxval = number(reader)?.clamp(1, 65535);
+    check_size(width, height)?;
+
+    let values: Vec<usize> = match magic.as_str() {
+        "P5" if maxval < 256 => {
+            let mut pixels = vec![0; width * height];
+            reader.read_exact(&mut pixels)?;
+            pixels.into_iter().map(usize::from).collect()
+        }
+        "P5" => {
+            let mut pixels = vec![0; width * height * 2];
+            reader.read_exact(&mut pixels)?;
+            pixels
+                .chunks(2)
+                .map(|p| usize::from(u16::from_be_bytes([p[0], p[1]])))
+                .collect()
+        }
+        "P2" => (0..width * height)
+            .map(|_| number(reader))
+            .collect::<io::Result<_>>()?,
+        _ => return Err(invalid("Only P5 and P2 PGM images are supported")),
+    };
+
+    Ok(Image {
+        width,
+        height,
+        pixels: values
+            .into_iter()
+            .map(|v| (v.min(maxval) * 255 / maxval) as u8)
+            .collect(),
+    })
+}
+
+impl UpdatableWidget for Image {
+    fn update(&mut self) {}
+
+    fn get_matrix(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: self.width,
+            y: self.height,
+        }
+    }
+}
+
+///
+/// Images drawn over the widgets on a module until they time out, either from
+/// another program or played when something happens (e.g. a notification)
+///
+pub struct Overlay {
+    /// Name given by the program, so the overlay can be replaced or cleared
+    pub id: Option<String>,
+    pub module: ModuleSelector,
+    pub x: i32,
+    pub y: i32,
+    /// Overlays with a higher priority are drawn on top
+    pub priority: i32,
+    /// Images shown one after another, repeating until the overlay times out.
+    /// A None frame leaves the widgets underneath showing.
+    pub frames: Vec<Option<Image>>,
+    /// How long each frame is shown for
+    pub frame_period: Duration,
+    /// When the first frame was shown
+    pub shown: Instant,
+    /// When to stop drawing the overlay, or None to keep it until it's cleared
+    pub expires: Option<Instant>,
+}
+
+impl Overlay {
+    ///
+    /// An overlay showing a single image
+    ///
+    pub fn still(
+        id: Option<String>,
+        module: ModuleSelector,
+        x: i32,
+        y: i32,
+        priority: i32,
+        image: Image,
+        expires: Option<Instant>,
+    ) -> Overlay {
+        Overlay {
+            id,
+            module,
+            x,
+            y,
+            priority,
+            frames: vec![Some(image)],
+            frame_period: Duration::MAX,
+            shown: Instant::now(),
+            expires,
+        }
+    }
+
+    ///
+    /// Number of frame periods since the overlay was shown
+    ///
+    fn periods(&self, now: Instant) -> u128 {
+        now.saturating_duration_since(self.shown).as_nanos() / self.frame_period.as_nanos().max(1)
+    }
+
+    ///
+    /// The frame shown at a point in time
+    ///
+    fn frame(&self, now: Instant) -> Option<&Image> {
+        match self.frames.len() {
+            0 => None,
+            n => self.frames[(self.periods(now) % n as u128) as usize].as_ref(),
+        }
+    }
+
+    ///
+    /// When the frame after the one shown at a point in time starts, or None for
+    /// a still image
+    ///
+    fn next_frame(&self, now: Instant) -> Option<Instant> {
+        if self.frames.len() < 2 {
+            return None;
+        }
+        let periods = u32::try_from(self.periods(now) + 1).ok()?;
+        self.frame_period
+            .checked_mul(periods)
+            .and_then(|elapsed| self.shown.checked_add(elapsed))
+    }
+}
+
+///
+/// Every overlay currently shown, in the order they were added
+///
+pub struct Overlays {
+    overlays: Vec<Overlay>,
+    /// Time the overlays are drawn at, so frames only change on a tick
+    now: Instant,
+}
+
+impl Default for Overlays {
+    fn default() -> Overlays {
+        Overlays {
+            overlays: vec![],
+            now: Instant::now(),
+        }
+    }
+}
+
+impl Overlays {
+    ///
+    /// Show a new overlay, replacing any overlay with the same id
+    ///
+    pub fn push(&mut self, overlay: Overlay) {
+        if overlay.id.is_some() {
+            self.overlays.retain(|o| o.id != overlay.id);
+        }
+        self.overlays.push(overlay);
+    }
+
+    ///
+    /// Remove the overlay with an id, or every overlay if None. Returns the
+    /// number removed.
+    ///
+    pub fn clear(&mut self, id: Option<&str>) -> usize {
+        let before = self.overlays.len();
+        self.overlays.retain(|o| id.is_some() && o.id.as_deref() != id);
+        before - self.overlays.len()
+    }
+
+    ///
+    /// Move on to a new point in time, removing overlays that have timed out.
+    /// Returns true if any were removed or an animation has moved to another frame.
+    ///
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let before = self.overlays.len();
+        self.overlays.retain(|o| o.expires.is_none_or(|t| t > now));
+        let animated = self
+            .overlays
+            .iter()
+            .any(|o| o.next_frame(self.now).is_some_and(|t| t <= now));
+
+        self.now = now;
+        animated || before != self.overlays.len()
+    }
+
+    ///
+    /// When the next overlay times out or moves to another frame
+    ///
+    pub fn next_change(&self) -> Option<Instant> {
+        self.overlays
+            .iter()
+            .flat_map(|o| [o.expires, o.next_frame(self.now)])
+            .flatten()
+            .min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.overlays.len()
+    }
+
+    ///
+    /// Draw every overlay onto the module frames, lowest priority first.
+    /// Overlays with the same priority are drawn in the order they were added.
+    ///
+    pub fn draw(&self, frames: &mut [Canvas], modules: &[Module]) {
+        let mut sorted: Vec<&Overlay> = self.overlays.iter().collect();
+        sorted.sort_by_key(|o| o.priority);
+
+        for overlay in sorted {
+            let (Some(idx), Some(image)) = (overlay.module.resolve(modules), overlay.frame(self.now))
+            else {
+                continue;
+            };
+            // Images are allowed to hang off the edge
+            let _ = frames[idx].emplace(image, overlay.x, overlay.y);
+        }
+    }
+}
+
+///
+/// Time an overlay stays up for, with 0 meaning until it's cleared
+///
+pub fn expiry(timeout_ms: u64, now: Instant) -> Option<Instant> {
+    match timeout_ms {
+        0 => None,
+        ms => Some(now + Duration::from_millis(ms)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(data: &[u8], format: ImageFormat, width: usize, height: usize) -> io::Result<Image> {
+        Image::read(&mut &data[..], format, width, height)
+    }
+
+    fn image(width: usize, height: usize, pixels: &[u8]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        }
+    }
+
+    #[test]
+    fn raw() {
+        let img = read(&[1, 2, 3, 4, 5, 6], ImageFormat::Raw, 3, 2).unwrap();
+        assert_eq!(img, image(3, 2, &[1, 2, 3, 4, 5, 6]));
+
+        let short = read(&[1, 2, 3], ImageFormat::Raw, 3, 2).unwrap_err();
+        assert_eq!(short.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn plain_pgm_with_comments() {
+        let data = b"P2\n# made by hand\n3 2 # width and height\n4\n0 2 4\n# last row\n4 2 0\n";
+        let img = read(data, ImageFormat::Pgm, 0, 0).unwrap();
+        assert_eq!(img, image(3, 2, &[0, 127, 255, 255, 127, 0]));
+    }
+
+    #[test]
+    fn binary_pgm() {
+        let mut data = b"P5\n# comment\n2 2\n255\n".to_vec();
+        // The first pixel is whitespace, which mustn't be skipped as part of the header
+        data.extend([b'\n', 10, 200, 255]);
+        let img = read(&data, ImageFormat::Pgm, 0, 0).unwrap();
+        assert_eq!(img, image(2, 2, &[10, 10, 200, 255]));
+    }
+
+    #[test]
+    fn sixteen_bit_pgm() {
+        let mut data = b"P5 3 1 65535\n".to_vec();
+        data.extend([0x00, 0x00, 0x80, 0x00, 0xFF, 0xFF]);
+        let img = read(&data, ImageFormat::Pgm, 0, 0).unwrap();
+        assert_eq!(img, image(3, 1, &[0, 127, 255]));
+    }
+
+    #[test]
+    fn bad_pgm() {
+        let wrong_magic = read(b"P6 1 1 255\n\0\0\0", ImageFormat::Pgm, 0, 0).unwrap_err();
+        assert_eq!(wrong_magic.kind(), io::ErrorKind::InvalidData);
+
+        let not_a_number = read(b"P2 1 one 255\n0", ImageFormat::Pgm, 0, 0).unwrap_err();
+        assert_eq!(not_a_number.kind(), io::ErrorKind::InvalidData);
+
+        let truncated = read(b"P2 2 2 255\n0 0 0", ImageFormat::Pgm, 0, 0).unwrap_err();
+        assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized() {
+        // Largest allowed, then one pixel too wide or too many
+        assert!(read(&[0; MAX_IMAGE_PIXELS], ImageFormat::Raw, MAX_IMAGE_PIXELS / 34, 34).is_ok());
+        assert!(read(&[], ImageFormat::Raw, MAX_IMAGE_SIDE + 1, 1).is_err());
+        assert!(read(&[], ImageFormat::Raw, MAX_IMAGE_PIXELS / 34 + 1, 34).is_err());
+
+        // Sides that would overflow if multiplied are turned down before anything is read
+        let huge = read(&[], ImageFormat::Raw, usize::MAX, usize::MAX).unwrap_err();
+        assert_eq!(huge.kind(), io::ErrorKind::InvalidData);
+        let huge = format!("P5 {} {} 255\n", usize::MAX, usize::MAX);
+        let huge = read(huge.as_bytes(), ImageFormat::Pgm, 0, 0).unwrap_err();
+        assert_eq!(huge.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(read(&[], ImageFormat::Raw, 0, 34).is_err());
+        assert!(read(b"P2 0 0 255\n", ImageFormat::Pgm, 0, 0).is_err());
+        assert!(read(b"[]\n", ImageFormat::Json, 0, 0).is_err());
+        assert!(read(b"[[], []]\n", ImageFormat::Json, 0, 0).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let img = read(b"[[0, 255, 0], [255, 0, 255]]\n", ImageFormat::Json, 0, 0).unwrap();
+        assert_eq!(img, image(3, 2, &[0, 255, 0, 255, 0, 255]));
+
+        let ragged = read(b"[[0, 255, 0], [255, 0]]\n", ImageFormat::Json, 0, 0).unwrap_err();
+        assert_eq!(ragged.kind(), io::ErrorKind::InvalidData);
+
+        let not_bytes = read(b"[[0, 256]]\n", ImageFormat::Json, 0, 0).unwrap_err();
+        assert_eq!(not_bytes.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::transport::VirtualHandle;
+use std::fmt::Write;
+
+/// Move the cursor to the top left corner of the terminal
+const CURSOR_HOME: &str = "\x1b[H";
+/// Clear the whole terminal
+const CLEAR: &str = "\x1b[2J";
+const RESET: &str = "\x1b[0m";
+/// Space between modules drawn side by side
+const GAP: &str = "  ";
+
+///
+/// Clear the terminal before the first preview frame is drawn
+///
+pub fn clear() -> String {
+    format!("{}{}", CLEAR, CURSOR_HOME)
+}
+
+///
+/// Render what each virtual module is displaying, side by side, as terminal text.
+/// Each character is a Unicode upper half block covering two LEDs: the top LED
+/// sets the foreground colour and the bottom LED sets the background colour, so
+/// the 9x34 module fits in 9x17 characters. LEDs are drawn in greyscale, scaled
+/// by the module's overall brightness.
+///
+pub fn render(modules: &[VirtualHandle]) -> String {
+    let states: Vec<_> = modules.iter().map(|m| m.lock().unwrap()).collect();
+    let mut out = String::from(CURSOR_HOME);
+
+    let border = |out: &mut String, left: char, right: char| {
+        for i in 0..states.len() {
+            if i > 0 {
+                out.push_str(GAP);
+            }
+            out.push(left);
+            out.push_str(&"─".repeat(9));
+            out.push(right);
+        }
+        out.push('\n');
+    };
+
+    border(&mut out, '┌', '┐');
+    for row in (0..34).step_by(2) {
+        for (i, state) in states.iter().enumerate() {
+            if i > 0 {
+                out.push_str(GAP);
+            }
+            out.push('│');
+            for col in 0..9 {
+                let level = |px: u8| {
+                    if state.sleeping {
+                        0
+                    } else {
+                        (px as u16 * state.brightness as u16 / 255) as u8
+                    }
+                };
+                let top = level(state.framebuffer[row][col]);
+                let bottom = level(state.framebuffer[row + 1][col]);
+                let _ = write!(
+                    out,
+                    "\x1b[38;2;{t};{t};{t}m\x1b[48;2;{b};{b};{b}m▀",
+                    t = top,
+                    b = bottom
+                );
+            }
+            out.push_str(RESET);
+            out.push('│');
+        }
+        out.push('\n');
+    }
+    border(&mut out, '└', '┘');
+
+    out
+}
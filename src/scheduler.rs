@@ -0,0 +1,72 @@
+use crate::layout::Placement;
+use crate::widget::{Shape, UpdatableWidget};
+use std::time::{Duration, Instant};
+
+///
+/// When a widget is next due to be updated, and what it drew last time
+///
+pub struct Schedule {
+    pub interval: Duration,
+    next_due: Instant,
+    last_output: Option<(Shape, Vec<u8>)>,
+}
+
+impl Schedule {
+    ///
+    /// A schedule that is due straight away
+    ///
+    pub fn new(interval: Duration) -> Schedule {
+        Schedule {
+            interval,
+            next_due: Instant::now(),
+            last_output: None,
+        }
+    }
+
+    pub fn next_due(&self) -> Instant {
+        self.next_due
+    }
+
+    ///
+    /// Update the widget if it's due. Returns true if what it draws has changed.
+    ///
+    pub fn run(&mut self, widget: &mut dyn UpdatableWidget, now: Instant) -> bool {
+        if now < self.next_due {
+            return false;
+        }
+
+        // Schedule from the previous due time so updates don't drift, unless
+        // we've fallen more than a whole interval behind
+        self.next_due += self.interval;
+        if self.next_due <= now {
+            self.next_due = now + self.interval;
+        }
+
+        widget.update();
+        let output = (widget.get_shape(), widget.get_matrix());
+        if self.last_output.as_ref() == Some(&output) {
+            return false;
+        }
+        self.last_output = Some(output);
+        true
+    }
+}
+
+///
+/// Update every widget that's due. Returns true if any of them changed, meaning
+/// the modules need to be redrawn.
+///
+pub fn tick(placements: &mut [Placement], now: Instant) -> bool {
+    let mut changed = false;
+    for p in placements.iter_mut() {
+        changed |= p.schedule.run(p.widget.as_mut(), now);
+    }
+    changed
+}
+
+///
+/// The next time any widget is due to be updated
+///
+pub fn next_due(placements: &[Placement]) -> Option<Instant> {
+    placements.iter().map(|p| p.schedule.next_due()).min()
+}
@@ -0,0 +1,382 @@
+use crate::ledmatrix::{
+    Pattern, ANIMATE_CMD, ANIMATION_PERIOD_CMD, BRIGHTNESS_CMD, CHECKFW_CMD, CMD_START,
+    COMMIT_COL, DRAW_CMD, PATTERN_CMD, PATTERN_PERCENTAGE, PWM_FREQ_CMD, RESPONSE_SIZE, SET_COL,
+    SLEEP_CMD,
+};
+use crate::matrix::Matrix;
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+///
+/// A byte stream to an LED matrix module. Every command is sent with a single
+/// write_all call, and replies are read back with bytes_to_read/read.
+///
+pub trait MatrixTransport: Read + Write + Send {
+    ///
+    /// Number of bytes waiting to be read
+    ///
+    fn bytes_to_read(&self) -> io::Result<u32>;
+}
+
+// ================ Serial ================
+
+/// -------- Serial Transport --------
+/// A real module connected over USB serial
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn new(port: Box<dyn serialport::SerialPort>) -> SerialTransport {
+        SerialTransport { port }
+    }
+}
+
+impl Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl MatrixTransport for SerialTransport {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(self.port.bytes_to_read()?)
+    }
+}
+
+// ================ Virtual ================
+
+/// Firmware version reported by virtual modules (major, minor << 4 | patch, pre-release)
+const VIRTUAL_FW_VERSION: [u8; 3] = [0, 0x12, 0];
+
+///
+/// Everything a virtual module would be showing if it were real
+///
+pub struct VirtualModuleState {
+    /// What's currently displayed, indexed [row][column]
+    pub framebuffer: Matrix,
+    pub brightness: u8,
+    pub sleeping: bool,
+    pub animating: bool,
+    /// Milliseconds between steps of scrolling or of a game
+    pub animation_period: u16,
+    /// PWM frequency, as the index sent with PWM_FREQ_CMD
+    pub pwm_freq: u8,
+    /// Columns written with SET_COL that haven't been committed yet
+    pub staged: Matrix,
+    /// Number of commands received, including ones the virtual module ignores
+    pub commands_received: usize,
+}
+
+pub type VirtualHandle = Arc<Mutex<VirtualModuleState>>;
+
+/// -------- Virtual Module --------
+/// An in-memory module that decodes the command stream into its own framebuffer.
+/// Useful for running without hardware attached.
+pub struct VirtualModule {
+    state: VirtualHandle,
+    replies: VecDeque<u8>,
+}
+
+impl VirtualModule {
+    ///
+    /// Create a virtual module, along with a handle to inspect what it's displaying
+    ///
+    pub fn new() -> (VirtualModule, VirtualHandle) {
+        let state = Arc::new(Mutex::new(VirtualModuleState {
+            framebuffer: [[0; 9]; 34],
+            brightness: 255,
+            sleeping: false,
+            animating: false,
+            animation_period: 100,
+            pwm_freq: 0,
+            staged: [[0; 9]; 34],
+            commands_received: 0,
+        }));
+
+        (
+            VirtualModule {
+                state: state.clone(),
+                replies: VecDeque::new(),
+            },
+            state,
+        )
+    }
+
+    ///
+    /// Apply one command (without the CMD_START prefix) to the module state
+    ///
+    fn handle(&mut self, cmd: u8, params: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.commands_received += 1;
+
+        let mut reply: Vec<u8> = vec![];
+        match (cmd, params) {
+            (BRIGHTNESS_CMD, []) => reply.push(state.brightness),
+            (BRIGHTNESS_CMD, [val, ..]) => state.brightness = *val,
+            (SLEEP_CMD, []) => reply.push(state.sleeping as u8),
+            (SLEEP_CMD, [val, ..]) => state.sleeping = *val != 0,
+            (ANIMATE_CMD, []) => reply.push(state.animating as u8),
+            (ANIMATE_CMD, [val, ..]) => state.animating = *val != 0,
+            (ANIMATION_PERIOD_CMD, []) => reply.extend(state.animation_period.to_le_bytes()),
+            (ANIMATION_PERIOD_CMD, [lo, hi, ..]) => {
+                state.animation_period = u16::from_le_bytes([*lo, *hi])
+            }
+            (PWM_FREQ_CMD, []) => reply.push(state.pwm_freq),
+            (PWM_FREQ_CMD, [val, ..]) => state.pwm_freq = *val,
+            // Only the simplest patterns are drawn, the rest are ignored
+            (PATTERN_CMD, [pattern, ..]) if *pattern == Pattern::FullBrightness as u8 => {
+                state.framebuffer = [[0xFF; 9]; 34]
+            }
+            (PATTERN_CMD, [PATTERN_PERCENTAGE, percent, ..]) => {
+                let lit = 34 * (*percent).min(100) as usize / 100;
+                for (i, row) in state.framebuffer.iter_mut().enumerate() {
+                    *row = [if i >= 34 - lit { 0xFF } else { 0x00 }; 9];
+                }
+            }
+            (DRAW_CMD, bits) if bits.len() >= 39 => {
+                for i in 0..34 {
+                    for j in 0..9 {
+                        let bit = i * 9 + j;
+                        let on = bits[bit / 8] & (1 << (bit % 8)) != 0;
+                        state.framebuffer[i][j] = if on { 0xFF } else { 0x00 };
+                    }
+                }
+            }
+            (SET_COL, [col, vals @ ..]) if (*col as usize) < 9 && vals.len() >= 34 => {
+                for (i, val) in vals.iter().take(34).enumerate() {
+                    state.staged[i][*col as usize] = *val;
+                }
+            }
+            (COMMIT_COL, _) => state.framebuffer = state.staged,
+            (CHECKFW_CMD, _) => reply.extend(VIRTUAL_FW_VERSION),
+            (DRAW_CMD, _) | (SET_COL, _) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Malformed parameters for command {:#04x}", cmd),
+                ))
+            }
+            _ => {}
+        }
+
+        // Like the firmware, every reply is padded out to a fixed size
+        if !reply.is_empty() {
+            reply.resize(RESPONSE_SIZE, 0);
+            self.replies.extend(reply);
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for VirtualModule {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.replies.pop_front() {
+                Some(b) => buf[n] = b,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for VirtualModule {
+    ///
+    /// Every write is treated as one complete command, the same way the firmware
+    /// treats every USB packet.
+    ///
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match buf {
+            [s0, s1, cmd, params @ ..] if [*s0, *s1] == CMD_START => {
+                self.handle(*cmd, params)?;
+                Ok(buf.len())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Command does not start with CMD_START",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MatrixTransport for VirtualModule {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(self.replies.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledmatrix::{LedMatrix, LedMatrixError, Pattern, PwmFreq, PWM_FREQ_CMD};
+    use crate::matrix::Matrix;
+    use clap::ValueEnum;
+    use std::time::Duration;
+
+    /// A frame where every pixel is different, so transposition mistakes show up
+    fn gradient() -> Matrix {
+        let mut mat = [[0; 9]; 34];
+        for (i, row) in mat.iter_mut().enumerate() {
+            for (j, px) in row.iter_mut().enumerate() {
+                *px = (i * 9 + j) as u8;
+            }
+        }
+        mat
+    }
+
+    #[test]
+    fn draw_matrix_shows_frame() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.draw_matrix(gradient()).unwrap();
+        assert_eq!(handle.lock().unwrap().framebuffer, gradient());
+    }
+
+    #[test]
+    fn draw_bool_matrix_shows_frame() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        let mut frame = [[false; 9]; 34];
+        frame[0][0] = true;
+        frame[5][3] = true;
+        frame[33][8] = true;
+        mat.draw_bool_matrix(frame).unwrap();
+
+        let expected = frame.map(|row| row.map(|on| if on { 0xFF } else { 0x00 }));
+        assert_eq!(handle.lock().unwrap().framebuffer, expected);
+    }
+
+    #[test]
+    fn columns_shown_on_commit() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        let col: [u8; 34] = std::array::from_fn(|i| i as u8 + 1);
+        mat.set_col(2, col).unwrap();
+        assert_eq!(handle.lock().unwrap().framebuffer, [[0; 9]; 34]);
+
+        mat.commit_col().unwrap();
+        let state = handle.lock().unwrap();
+        for (i, row) in state.framebuffer.iter().enumerate() {
+            assert_eq!(row[2], col[i]);
+            assert_eq!(row[1], 0);
+            assert_eq!(row[3], 0);
+        }
+    }
+
+    #[test]
+    fn brightness() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        for val in [40, 0, 255] {
+            mat.set_full_brightness(val).unwrap();
+            assert_eq!(handle.lock().unwrap().brightness, val);
+            assert_eq!(mat.get_brightness().unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn sleep() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.sleep().unwrap();
+        assert!(handle.lock().unwrap().sleeping);
+        assert!(mat.is_sleeping().unwrap());
+        mat.wake().unwrap();
+        assert!(!mat.is_sleeping().unwrap());
+    }
+
+    #[test]
+    fn animate() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.set_animate(true).unwrap();
+        assert!(handle.lock().unwrap().animating);
+        assert!(mat.is_animating().unwrap());
+        mat.stop_animation().unwrap();
+        assert!(!mat.is_animating().unwrap());
+    }
+
+    #[test]
+    fn animation_period() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        // Both bytes of the period have to survive the trip
+        mat.set_animation_period(Duration::from_millis(300)).unwrap();
+        assert_eq!(handle.lock().unwrap().animation_period, 300);
+        assert_eq!(mat.get_animation_period().unwrap(), Duration::from_millis(300));
+
+        mat.set_animation_period(Duration::from_secs(100)).unwrap();
+        assert_eq!(mat.get_animation_period().unwrap(), Duration::from_millis(u16::MAX as u64));
+    }
+
+    #[test]
+    fn pwm_freq() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        for freq in PwmFreq::value_variants() {
+            mat.set_pwm_freq(*freq).unwrap();
+            assert_eq!(handle.lock().unwrap().pwm_freq, *freq as u8);
+            assert_eq!(mat.get_pwm_freq().unwrap(), *freq);
+        }
+
+        handle.lock().unwrap().pwm_freq = 9;
+        assert!(matches!(
+            mat.get_pwm_freq(),
+            Err(LedMatrixError::InvalidReply { cmd: PWM_FREQ_CMD, reply: 9 })
+        ));
+    }
+
+    #[test]
+    fn full_brightness_pattern() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.draw_pattern(Pattern::FullBrightness).unwrap();
+        assert_eq!(handle.lock().unwrap().framebuffer, [[0xFF; 9]; 34]);
+    }
+
+    #[test]
+    fn unchanged_frame_sends_nothing() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.draw_matrix(gradient()).unwrap();
+        let sent = handle.lock().unwrap().commands_received;
+        mat.draw_matrix(gradient()).unwrap();
+        assert_eq!(handle.lock().unwrap().commands_received, sent);
+    }
+
+    #[test]
+    fn changed_column_sends_one_column() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.draw_matrix(gradient()).unwrap();
+        let sent = handle.lock().unwrap().commands_received;
+
+        let mut frame = gradient();
+        frame[10][4] = 0xFF;
+        mat.draw_matrix(frame).unwrap();
+
+        let state = handle.lock().unwrap();
+        // One SET_COL and one COMMIT_COL
+        assert_eq!(state.commands_received, sent + 2);
+        assert_eq!(state.framebuffer, frame);
+    }
+
+    #[test]
+    fn invalidate_resends_every_column() {
+        let (mut mat, handle) = LedMatrix::new_virtual("virtual0");
+        mat.draw_matrix(gradient()).unwrap();
+        let sent = handle.lock().unwrap().commands_received;
+
+        mat.invalidate();
+        mat.draw_matrix(gradient()).unwrap();
+        assert_eq!(handle.lock().unwrap().commands_received, sent + 9 + 1);
+    }
+}
@@ -1,9 +1,31 @@
-use chrono::{Local, Timelike};
+use crate::config::MODULE_HEIGHT;
+use crate::font::{render_text, FontFace};
+use crate::metric::{network_log_scale, MetricSource, NetworkSampler};
+use chrono::{Datelike, NaiveDateTime, Timelike, Utc};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
 
-const ON_FULL: u8 = 120;
-const ON_DIM: u8 = 68;
-const OFF: u8 = 0;
+pub(crate) const ON_FULL: u8 = 120;
+pub(crate) const ON_DIM: u8 = 68;
+pub(crate) const OFF: u8 = 0;
 
+/// How often widgets are updated, unless they say otherwise
+const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often CPU widgets are updated. CPU usage is averaged over the time
+/// between updates, so a longer interval gives a steadier reading
+const CPU_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(PartialEq, Clone, Copy)]
 pub struct Shape {
     pub x: usize,
     pub y: usize,
@@ -14,79 +36,67 @@ pub trait UpdatableWidget {
     fn update(&mut self);
     fn get_matrix(&self) -> Vec<u8>;
     fn get_shape(&self) -> Shape;
+
+    /// How often update should be called
+    fn update_interval(&self) -> Duration {
+        DEFAULT_UPDATE_INTERVAL
+    }
+}
+
+///
+/// Fill a row of pixels as a bar showing a fraction (0.0 - 1.0). The pixel at the
+/// end of the bar is lit partially, so the bar moves smoothly rather than a
+/// whole pixel at a time.
+///
+fn render_bar(row: &mut [u8], fraction: f32, brightness: u8) {
+    let bar_width_in_pixels = fraction * row.len() as f32;
+    for (x, px) in row.iter_mut().enumerate() {
+        let percent_on = bar_width_in_pixels - x as f32;// this is a float telling how much the pixel should be on
+        if percent_on > 1.0 {//if we are more than 100% on
+            *px = brightness;
+        }
+        else if percent_on > 0.0//if we are fractionally on - the end of the bar
+        {
+            *px = (brightness as f32 * percent_on) as u8;
+        }
+    }
+}
+
+///
+/// Fill a column of pixels as a vertical bar showing a fraction (0.0 - 1.0),
+/// growing from the bottom of the column, or from the top if grow_down is set.
+/// Like render_bar, the tip of the bar is lit partially.
+///
+fn render_vbar(column: &mut [&mut u8], fraction: f32, brightness: u8, grow_down: bool) {
+    let mut pixels = vec![0; column.len()];
+    render_bar(&mut pixels, fraction, brightness);
+    if !grow_down {
+        pixels.reverse();
+    }
+    for (px, val) in column.iter_mut().zip(pixels) {
+        **px = val;
+    }
+}
+
+///
+/// Dotted line shown in place of a bar when there's nothing to measure, e.g. no
+/// battery, a disk that isn't mounted or a sensor that wasn't found
+///
+fn render_missing(row: &mut [u8]) {
+    for px in row.iter_mut().step_by(2) {
+        *px = ON_DIM / 2;
+    }
 }
 
 // ================ Frames ================
 /// Battery frame with empty interior (9x4 shape)
-const BAT_FRAME: &'static [u8] = [
+const BAT_FRAME: &[u8] = [
     ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, OFF, OFF,
     OFF, OFF, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, OFF, OFF, OFF, ON_FULL, ON_FULL,
     ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
 ]
 .as_slice();
 
-const DIGIT_0: &'static [u8] = [
-    OFF, ON_FULL, OFF, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, OFF,
-    ON_FULL, OFF,
-]
-.as_slice();
-
-const DIGIT_1: &'static [u8] = [
-    OFF, OFF, ON_FULL, OFF, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF,
-    ON_FULL,
-]
-.as_slice();
-
-const DIGIT_2: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_3: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_4: &'static [u8] = [
-    ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    OFF, OFF, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_5: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_6: &'static [u8] = [
-    OFF, ON_FULL, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_7: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_DIM, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, ON_FULL, OFF, OFF,
-    ON_FULL, OFF,
-]
-.as_slice();
-
-const DIGIT_8: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
-
-const DIGIT_9: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    ON_DIM, ON_FULL, OFF,
-]
-.as_slice();
-
-
-
 // ================ Widgets ================
 
 /// -------- RAM Widget --------
@@ -118,122 +128,360 @@ impl UpdatableWidget for RAMWidget {
 
         let width = self.get_shape().x;
 
-        let bar_width_in_pixels = self.ram_perc * width as f32;
-        for x in 0..width {
-            let percent_on = bar_width_in_pixels - x as f32;// this is a float telling how much the pixel should be on
-            if percent_on > 1.0 {//if we are more than 100% on
-                out[x] = ON_FULL;
+        render_bar(&mut out[..width], self.ram_perc, ON_FULL);
+        out.copy_within(0..width, width);
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape { x: 9, y: 2 }
+    }
+}
+
+/// File systems that aren't on a disk, left out when no disks are chosen
+const DISK_PSEUDO_FS: &[&str] = &["squashfs", "tmpfs", "devtmpfs", "ramfs", "overlay", "efivarfs"];
+
+/// -------- Disk Widget --------
+/// Create a widget that displays how full disks are, one bar per disk
+pub struct DiskWidget {
+    /// Mount points or device names to show, one row each
+    selected: Vec<String>,
+    bar_height: usize,
+    /// Fraction of each disk in use, None if the disk isn't mounted
+    usages: Vec<Option<f32>>,
+    disks: sysinfo::Disks,
+}
+
+impl DiskWidget {
+    pub fn new(selected: Vec<String>, bar_height: usize) -> DiskWidget {
+        println!("Initializing DiskWidget");
+        let bar_height = bar_height.max(1);
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+
+        // The rows are settled here, so the widget stays the size it was laid out
+        // at as disks come and go
+        let selected = match selected.is_empty() {
+            true => Self::mounted(&disks, MODULE_HEIGHT / bar_height),
+            false => selected,
+        };
+
+        let mut widget = DiskWidget {
+            usages: vec![None; selected.len()],
+            selected,
+            bar_height,
+            disks,
+        };
+        widget.update();
+        widget
+    }
+
+    ///
+    /// Mount points of the disks mounted right now, leaving out loop devices (e.g.
+    /// snaps) and file systems that aren't on a disk, as many as fit on a module
+    ///
+    fn mounted(disks: &sysinfo::Disks, max: usize) -> Vec<String> {
+        disks
+            .list()
+            .iter()
+            .filter(|d| !DISK_PSEUDO_FS.contains(&d.file_system().to_string_lossy().as_ref()))
+            .filter(|d| !d.name().to_string_lossy().starts_with("/dev/loop"))
+            .map(|d| d.mount_point().to_string_lossy().into_owned())
+            .take(max)
+            .collect()
+    }
+
+    ///
+    /// Whether a disk matches a mount point or device name from the config.
+    /// Device names can be given with or without the leading "/dev/".
+    ///
+    fn matches(disk: &sysinfo::Disk, selector: &str) -> bool {
+        let name = disk.name().to_string_lossy();
+        disk.mount_point() == Path::new(selector)
+            || name == selector
+            || name.strip_prefix("/dev/") == Some(selector)
+    }
+}
+
+impl UpdatableWidget for DiskWidget {
+    fn update(&mut self) {
+        // Refresh the whole list, so disks that are mounted or unmounted are picked up
+        self.disks.refresh_list();
+
+        let usage = |disk: &sysinfo::Disk| match disk.total_space() {
+            0 => 0.0,
+            total => 1.0 - disk.available_space() as f32 / total as f32,
+        };
+
+        self.usages = self
+            .selected
+            .iter()
+            .map(|sel| {
+                self.disks
+                    .list()
+                    .iter()
+                    .find(|d| Self::matches(d, sel))
+                    .map(usage)
+            })
+            .collect();
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let width = self.get_shape().x;
+        let mut out: Vec<u8> = vec![OFF; width * self.get_shape().y];
+
+        for (idx, usage) in self.usages.iter().enumerate() {
+            let start = idx * self.bar_height * width;
+            match usage {
+                Some(fraction) => render_bar(&mut out[start..start + width], *fraction, ON_FULL),
+                None => render_missing(&mut out[start..start + width]),
             }
-            else if percent_on > 0.0//if we are fractionally on - the end of the bar
-            {
-                out[x] = (ON_FULL as f32 * percent_on) as u8;
+            for row in 1..self.bar_height {
+                out.copy_within(start..start + width, start + row * width);
             }
-            out[x + width] = out[x];
         }
 
         out
     }
 
     fn get_shape(&self) -> Shape {
-        return Shape { x: 9, y: 2 };
+        Shape {
+            x: 9,
+            y: self.usages.len() * self.bar_height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+}
+
+
+/// -------- Battery Status --------
+/// Hours shown by a full battery time row, one pixel per hour
+const BAT_TIME_FULL_SCALE: f32 = 9.0;
+/// Watts shown by a full battery charge rate row, ten watts per pixel
+const BAT_RATE_FULL_SCALE: f32 = 90.0;
+
+///
+/// Every battery in the laptop added together
+///
+#[derive(Clone, Copy)]
+struct BatteryStatus {
+    level_pct: f32,
+    state: battery::State,
+    /// Rate energy is going in or out, in watts
+    rate: f32,
+    /// Time until empty while discharging, or until full while charging, in hours
+    time_left: Option<f32>,
+}
+
+///
+/// Reads the status of every battery. Laptops without a battery, or without a
+/// working battery driver, have no status rather than being an error.
+///
+struct BatteryReader {
+    manager: Option<battery::Manager>,
+}
+
+impl BatteryReader {
+    fn new() -> BatteryReader {
+        let manager = match battery::Manager::new() {
+            Ok(manager) => Some(manager),
+            Err(e) => {
+                println!("Unable to read batteries: {}", e);
+                None
+            }
+        };
+        BatteryReader { manager }
+    }
+
+    ///
+    /// Add up the energy of every battery, or None if there aren't any
+    ///
+    fn read(&self) -> Option<BatteryStatus> {
+        use battery::units::{energy::watt_hour, power::watt};
+
+        let batteries: Vec<battery::Battery> = self
+            .manager
+            .as_ref()?
+            .batteries()
+            .ok()?
+            .filter_map(|b| b.ok())
+            .collect();
+        if batteries.is_empty() {
+            return None;
+        }
+
+        let energy: f32 = batteries.iter().map(|b| b.energy().get::<watt_hour>()).sum();
+        let energy_full: f32 = batteries.iter().map(|b| b.energy_full().get::<watt_hour>()).sum();
+        let rate: f32 = batteries.iter().map(|b| b.energy_rate().get::<watt>()).sum();
+        let any = |state| batteries.iter().any(|b| b.state() == state);
+
+        // Any battery charging or discharging means the whole pack is
+        let state = if any(battery::State::Charging) {
+            battery::State::Charging
+        } else if any(battery::State::Discharging) {
+            battery::State::Discharging
+        } else if batteries.iter().all(|b| b.state() == battery::State::Full) {
+            battery::State::Full
+        } else {
+            battery::State::Unknown
+        };
+
+        let time_left = match state {
+            _ if rate <= 0.0 => None,
+            battery::State::Discharging => Some(energy / rate),
+            battery::State::Charging => Some((energy_full - energy).max(0.0) / rate),
+            _ => None,
+        };
+
+        Some(BatteryStatus {
+            level_pct: match energy_full {
+                full if full > 0.0 => energy / full * 100.0,
+                _ => 0.0,
+            },
+            state,
+            rate,
+            time_left,
+        })
     }
 }
 
+///
+/// Optional rows drawn below a battery widget
+///
+#[derive(Clone, Copy)]
+pub struct BatteryDetails {
+    /// A row showing time to empty (or to full while charging), one pixel per hour
+    pub time: bool,
+    /// A row showing the charge or discharge rate, one pixel per 10 watts
+    pub rate: bool,
+}
+
+impl BatteryDetails {
+    fn rows(&self) -> usize {
+        self.time as usize + self.rate as usize
+    }
+
+    ///
+    /// Draw the detail rows. They're dimmer while charging, and empty when
+    /// there's no battery or nothing is known.
+    ///
+    fn render(&self, status: Option<&BatteryStatus>, out: &mut [u8], width: usize) {
+        let status = match status {
+            Some(status) => status,
+            None => return,
+        };
+        let brightness = match status.state {
+            battery::State::Charging => ON_DIM,
+            _ => ON_FULL,
+        };
+
+        let mut rows = out.chunks_mut(width);
+        if self.time {
+            let row = rows.next().unwrap();
+            if let Some(hours) = status.time_left {
+                render_bar(row, hours / BAT_TIME_FULL_SCALE, brightness);
+            }
+        }
+        if self.rate {
+            let row = rows.next().unwrap();
+            render_bar(row, (status.rate / BAT_RATE_FULL_SCALE).min(1.0), brightness);
+        }
+    }
+}
 
 /// -------- Battery Widget --------
 /// Create a widget that displays the battery remaining in the laptop
 pub struct BatteryWidget {
-    bat_level_pct: f32,
+    status: Option<BatteryStatus>,
+    details: BatteryDetails,
+    reader: BatteryReader,
 }
 
 impl BatteryWidget {
-    pub fn new() -> BatteryWidget {
+    pub fn new(details: BatteryDetails) -> BatteryWidget {
         println!("Initializing BatteryWidget");
-        BatteryWidget { bat_level_pct: 0.0}
+        BatteryWidget {
+            status: None,
+            details,
+            reader: BatteryReader::new(),
+        }
     }
 }
 
 impl UpdatableWidget for BatteryWidget {
     fn update(&mut self) {
         // Update the battery percentage
-        self.bat_level_pct = battery::Manager::new()
-            .unwrap()
-            .batteries()
-            .unwrap()
-            .enumerate()
-            .next()
-            .unwrap()
-            .1
-            .unwrap()
-            .state_of_charge()
-            .get::<battery::units::ratio::percent>();
+        self.status = self.reader.read();
     }
 
     fn get_matrix(&self) -> Vec<u8> {
         // Create the matrix
-        let mut out: Vec<u8> = Vec::new();
-        out.extend_from_slice(BAT_FRAME);
+        let width = self.get_shape().x;
+        let mut out: Vec<u8> = vec![OFF; width * self.get_shape().y];
+        out[..BAT_FRAME.len()].copy_from_slice(BAT_FRAME);
 
-        let num_illum = (self.bat_level_pct * 6.0 / 100.0).round();
+        match self.status {
+            Some(ref status) => {
+                let num_illum = (status.level_pct * 6.0 / 100.0).round();
 
-        for i in 1..7 {
-            if i <= num_illum as usize {
-                out[(self.get_shape().x) + i] = ON_DIM;
-                out[(self.get_shape().x * 2) + i] = ON_DIM;
+                for i in 1..7 {
+                    if i <= num_illum as usize {
+                        out[width + i] = ON_DIM;
+                        out[(width * 2) + i] = ON_DIM;
+                    }
+                }
+            }
+            // Dotted outline with nothing inside when there's no battery
+            None => {
+                for (i, px) in out[..BAT_FRAME.len()].iter_mut().enumerate() {
+                    if *px != OFF {
+                        *px = if i % 2 == 0 { ON_DIM / 2 } else { OFF };
+                    }
+                }
             }
         }
 
+        self.details
+            .render(self.status.as_ref(), &mut out[BAT_FRAME.len()..], width);
         out
     }
 
     fn get_shape(&self) -> Shape {
-        return Shape { x: 9, y: 4 };
+        Shape {
+            x: 9,
+            y: 4 + self.details.rows(),
+        }
     }
 }
 
 /// -------- Battery Widget Ugly --------
 /// Create a widget that displays the battery remaining in the laptop
 pub struct BatteryWidgetUgly {
-    bat_level_pct: f32,
-    state: battery::State,
+    status: Option<BatteryStatus>,
+    details: BatteryDetails,
     looper: u8,
+    reader: BatteryReader,
 }
 
 impl BatteryWidgetUgly {
-    pub fn new() -> BatteryWidgetUgly {
+    pub fn new(details: BatteryDetails) -> BatteryWidgetUgly {
         println!("Initializing BatteryWidgetUgly");
-        BatteryWidgetUgly { bat_level_pct: 0.0, state: battery::State::Discharging, looper: 0 }
+        BatteryWidgetUgly {
+            status: None,
+            details,
+            looper: 0,
+            reader: BatteryReader::new(),
+        }
     }
 }
 
 impl UpdatableWidget for BatteryWidgetUgly {
     fn update(&mut self) {
-        // Update the battery percentage
-        let bat_man = battery::Manager::new();
-        self.bat_level_pct = bat_man.as_ref()
-            .unwrap()
-            .batteries()
-            .unwrap()
-            .enumerate()
-            .next()
-            .unwrap()
-            .1
-            .unwrap()
-            .state_of_charge()
-            .get::<battery::units::ratio::percent>();
-
-
-        let state = bat_man.as_ref()
-            .unwrap()
-            .batteries()
-            .unwrap()
-            .enumerate()
-            .next()
-            .unwrap()
-            .1
-            .unwrap()
-            .state();
-        self.state = state;
+        // Update the battery percentage and state
+        self.status = self.reader.read();
         self.looper = self.looper.wrapping_add(1);
     }
 
@@ -243,9 +491,18 @@ impl UpdatableWidget for BatteryWidgetUgly {
 
         let width = self.get_shape().x;
 
-        let bar_width_in_pixels = self.bat_level_pct / 100.0 * width as f32;
+        let status = match self.status {
+            Some(ref status) => status,
+            None => {
+                render_missing(&mut out[..width]);
+                out.copy_within(0..width, width);
+                return out;
+            }
+        };
+
+        let bar_width_in_pixels = status.level_pct / 100.0 * width as f32;
         for x in 0..width {
-            if self.state == battery::State::Full
+            if status.state == battery::State::Full
             {
                 out[x] = ON_DIM;
             }
@@ -261,53 +518,143 @@ impl UpdatableWidget for BatteryWidgetUgly {
                 }
                 if (x as f32) < bar_width_in_pixels
                 {
-                    if self.state == battery::State::Charging
+                    let phase = if status.state == battery::State::Charging {
+                        self.looper.wrapping_sub(x as u8)
+                    } else {
+                        self.looper.wrapping_add(x as u8)
+                    };
+                    if phase % (bar_width_in_pixels as u8 + 1) < 2
                     {
-                        if (self.looper.wrapping_sub(x as u8)) % (bar_width_in_pixels as u8 + 1) < 2
-                        {
-                            out[x] = 255;
-                        }
-                    }
-                    else
-                    {
-                        if (self.looper.wrapping_add(x as u8)) % (bar_width_in_pixels as u8 + 1) < 2
-                        {
-                            out[x] = 255;
-                        }
+                        out[x] = 255;
                     }
                 }
             }
             out[x + width] = out[x];
         }
+
+        self.details
+            .render(Some(status), &mut out[width * 2..], width);
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: 9,
+            y: 2 + self.details.rows(),
+        }
+    }
+}
+
+// -------- CPU Usage Widget --------
+/// Create a widget that displays the overall usage of the CPU as a bar
+pub struct CPUWidget {
+    usage: f32,
+    height: usize,
+    sys: sysinfo::System,
+}
+
+impl CPUWidget {
+    pub fn new(height: usize) -> CPUWidget {
+        let mut newsys = sysinfo::System::new();
+        newsys.refresh_cpu();
+
+        println!("Initializing CPUWidget");
+
+        CPUWidget {
+            usage: 0.0,
+            height: height.max(1),
+            sys: newsys,
+        }
+    }
+}
+
+impl UpdatableWidget for CPUWidget {
+    fn update(&mut self) {
+        // Refresh the cpu usage
+        self.sys.refresh_cpu();
+        self.usage = self.sys.global_cpu_info().cpu_usage();
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let width = self.get_shape().x;
+        let mut out = vec![OFF; width * self.height];
+
+        render_bar(&mut out[..width], self.usage / 100.0, ON_FULL);
+        for row in 1..self.height {
+            out.copy_within(0..width, row * width);
+        }
+
         out
     }
 
     fn get_shape(&self) -> Shape {
-        return Shape { x: 9, y: 2 };
+        Shape {
+            x: 9,
+            y: self.height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        CPU_UPDATE_INTERVAL
     }
 }
 
 // -------- All Cores CPU Usage Widget --------
 /// Create a widget that displays the usage of all CPU cores, one per row.
+/// With merge_threads, each core is instead a column, and neighbouring threads
+/// are averaged together.
+/// If there are more CPUs than rows (or columns), neighbouring CPUs are averaged
+/// together so they fit.
 pub struct AllCPUsWidget {
     cpu_usages: Vec<f32>,
     merge_threads: bool,
+    width: usize,
+    height: usize,
     sys: sysinfo::System,
 }
 
 impl AllCPUsWidget {
-    pub fn new(merge_threads: bool) -> AllCPUsWidget {
+    ///
+    /// Create the widget. The width and height default to one row per CPU, or
+    /// with merge_threads, one 8 pixel tall column per pair of threads.
+    ///
+    pub fn new(merge_threads: bool, width: Option<usize>, height: Option<usize>) -> AllCPUsWidget {
         let mut newsys = sysinfo::System::new();
         newsys.refresh_cpu();
+        let num_cpus = newsys.cpus().len().max(1);
 
         println!("Initializing AllCPUsWidget");
 
+        let (width, height) = match merge_threads {
+            false => (width.unwrap_or(9), height.unwrap_or(num_cpus).min(num_cpus)),
+            true => (
+                width.unwrap_or(num_cpus.div_ceil(2).min(9)).min(num_cpus),
+                height.unwrap_or(8),
+            ),
+        };
+
         AllCPUsWidget {
-            cpu_usages: vec![0.0; newsys.cpus().len()],
+            cpu_usages: vec![0.0; num_cpus],
             merge_threads,
+            width: width.max(1),
+            height: height.max(1),
             sys: newsys,
         }
     }
+
+    ///
+    /// Average the CPU usages into a number of evenly sized groups of
+    /// neighbouring CPUs
+    ///
+    fn grouped_usages(&self, groups: usize) -> Vec<f32> {
+        let n = self.cpu_usages.len();
+        (0..groups)
+            .map(|g| {
+                let group = &self.cpu_usages[g * n / groups..(g + 1) * n / groups];
+                group.iter().sum::<f32>() / group.len().max(1) as f32
+            })
+            .collect()
+    }
 }
 
 impl UpdatableWidget for AllCPUsWidget {
@@ -324,31 +671,247 @@ impl UpdatableWidget for AllCPUsWidget {
     fn get_matrix(&self) -> Vec<u8> {
         // Create the matrix
         let width = self.get_shape().x;
-		let height = self.get_shape().y;
+        let height = self.get_shape().y;
         let mut out = vec![OFF; width * height];
 
         if self.merge_threads {
-            for idy in 0..height {
-				let inverse_y = height - (idy + 1);
-                for (idx, chunk) in self.cpu_usages.chunks(2).enumerate() {
-                    let usage = (chunk[0] + chunk[1]) / 2.0;
-					if usage as usize >= inverse_y * 10 {
-						out[(idy * width) + idx] = ON_FULL;
-					}
-                }
+            for (x, usage) in self.grouped_usages(width).into_iter().enumerate() {
+                let mut column: Vec<&mut u8> = out.iter_mut().skip(x).step_by(width).collect();
+                render_vbar(&mut column, usage / 100.0, ON_FULL, false);
             }
         } else {
-            for y in 0..16 {
-                let bar_width_in_pixels = self.cpu_usages[y] / 100.0 * width as f32;
-                for x in 0..width {
-                    let percent_on = bar_width_in_pixels - x as f32;// this is a float telling how much the pixel should be on
-                    if percent_on > 1.0 {//if we are more than 100% on
-                        out[x + (y * width)] = ON_FULL;
-                    }
-                    else if percent_on > 0.0//if we are fractionally on - the end of the bar
-                    {
-                        out[x + (y * width)] = (ON_FULL as f32 * percent_on) as u8;
-                    }
+            for (y, usage) in self.grouped_usages(height).into_iter().enumerate() {
+                render_bar(&mut out[y * width..(y + 1) * width], usage / 100.0, ON_FULL);
+            }
+        }
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: self.width,
+            y: self.height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        CPU_UPDATE_INTERVAL
+    }
+}
+
+/// -------- Load Average Widget --------
+/// What LoadWidget counts below the load bars
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskCount {
+    #[default]
+    None,
+    Processes,
+    /// Every thread of every process (Linux only, processes are counted elsewhere)
+    Threads,
+}
+
+/// Create a widget that displays the 1, 5 and 15 minute load averages as bars,
+/// where a full bar is one runnable task per CPU. Optionally followed by a
+/// process or thread count, hundreds above units.
+pub struct LoadWidget {
+    /// 1, 5 and 15 minute load averages
+    loads: [f32; 3],
+    num_cpus: usize,
+    bar_height: usize,
+    count: TaskCount,
+    tasks: usize,
+    sys: sysinfo::System,
+}
+
+impl LoadWidget {
+    pub fn new(bar_height: usize, count: TaskCount) -> LoadWidget {
+        println!("Initializing LoadWidget");
+        let mut sys = sysinfo::System::new();
+        sys.refresh_cpu();
+
+        let mut widget = LoadWidget {
+            loads: [0.0; 3],
+            num_cpus: sys.cpus().len().max(1),
+            bar_height: bar_height.max(1),
+            count,
+            tasks: 0,
+            sys,
+        };
+        widget.update();
+        widget
+    }
+}
+
+impl UpdatableWidget for LoadWidget {
+    fn update(&mut self) {
+        let load = sysinfo::System::load_average();
+        self.loads = [load.one as f32, load.five as f32, load.fifteen as f32];
+
+        if self.count != TaskCount::None {
+            self.sys.refresh_processes_specifics(sysinfo::ProcessRefreshKind::new());
+            // Threads are listed alongside processes where they're known
+            let processes = self.sys.processes().values();
+            self.tasks = match self.count {
+                TaskCount::Threads => processes.count(),
+                _ => processes.filter(|p| p.thread_kind().is_none()).count(),
+            };
+        }
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let shape = self.get_shape();
+        let mut out: Vec<u8> = vec![OFF; shape.x * shape.y];
+
+        // Longer averages are dimmer
+        let brightnesses = [ON_FULL, ON_DIM, ON_DIM / 2];
+        for (idx, (load, brightness)) in self.loads.iter().zip(brightnesses).enumerate() {
+            let start = idx * self.bar_height * shape.x;
+            render_bar(
+                &mut out[start..start + shape.x],
+                (load / self.num_cpus as f32).min(1.0),
+                brightness,
+            );
+            for row in 1..self.bar_height {
+                out.copy_within(start..start + shape.x, start + row * shape.x);
+            }
+        }
+
+        if self.count != TaskCount::None {
+            let tasks = self.tasks.min(9999) as u32;
+            let start = (3 * self.bar_height + 1) * shape.x;
+            let mut readout = ClockWidget::render_number(tasks / 100);
+            readout.extend(vec![OFF; shape.x]);
+            readout.extend(ClockWidget::render_number(tasks % 100));
+            out[start..].copy_from_slice(&readout);
+        }
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        let readout = match self.count {
+            TaskCount::None => 0,
+            _ => 12,
+        };
+        Shape {
+            x: 9,
+            y: 3 * self.bar_height + readout,
+        }
+    }
+
+    /// The kernel only recalculates load averages every 5 seconds
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+}
+
+/// -------- Network Widget --------
+/// How NetworkWidget draws receive and transmit rates
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkStyle {
+    /// One bar for receive, with transmit below it
+    #[default]
+    Bars,
+    /// A scrolling graph, receive growing up from the middle and transmit growing down
+    Graph,
+}
+
+/// How NetworkWidget fits rates onto a 9 pixel bar
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkScale {
+    /// Logarithmic from 100 B/s to 125 MB/s (gigabit), so idle and busy
+    /// links are both readable
+    #[default]
+    Log,
+    /// Linear, scaled to the busiest sample in the last few updates
+    Auto,
+}
+
+/// Smallest full scale when auto-ranging, so idle noise doesn't fill the bar
+const NET_AUTO_FLOOR: f32 = 10_000.0;
+/// Number of samples kept for the graph and auto-ranging
+const NET_HISTORY: usize = 9;
+
+/// Create a widget that displays network receive and transmit rates
+pub struct NetworkWidget {
+    style: NetworkStyle,
+    scale: NetworkScale,
+    /// Rows per bar, or the graph height
+    height: usize,
+    /// (receive, transmit) rates in bytes per second, newest last
+    history: VecDeque<(f32, f32)>,
+    /// Measures the interface to show, or every non-loopback interface
+    sampler: NetworkSampler,
+}
+
+impl NetworkWidget {
+    pub fn new(
+        interface: Option<String>,
+        style: NetworkStyle,
+        scale: NetworkScale,
+        height: usize,
+    ) -> NetworkWidget {
+        println!("Initializing NetworkWidget");
+        NetworkWidget {
+            style,
+            scale,
+            height: height.max(1),
+            history: VecDeque::from(vec![(0.0, 0.0); NET_HISTORY]),
+            sampler: NetworkSampler::new(interface),
+        }
+    }
+
+    ///
+    /// Convert a rate to a fraction of the bar to fill
+    ///
+    fn scale(&self, rate: f32) -> f32 {
+        match self.scale {
+            NetworkScale::Log => network_log_scale(rate),
+            NetworkScale::Auto => {
+                let peak = self
+                    .history
+                    .iter()
+                    .fold(NET_AUTO_FLOOR, |peak, (rx, tx)| peak.max(*rx).max(*tx));
+                rate / peak
+            }
+        }
+        .min(1.0)
+    }
+}
+
+impl UpdatableWidget for NetworkWidget {
+    fn update(&mut self) {
+        self.history.pop_front();
+        self.history.push_back(self.sampler.sample());
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let shape = self.get_shape();
+        let mut out: Vec<u8> = vec![OFF; shape.x * shape.y];
+
+        match self.style {
+            NetworkStyle::Bars => {
+                let (rx, tx) = *self.history.back().unwrap();
+                let (rx_rows, tx_rows) = out.split_at_mut(self.height * shape.x);
+                render_bar(&mut rx_rows[..shape.x], self.scale(rx), ON_FULL);
+                render_bar(&mut tx_rows[..shape.x], self.scale(tx), ON_DIM);
+                for row in 1..self.height {
+                    rx_rows.copy_within(0..shape.x, row * shape.x);
+                    tx_rows.copy_within(0..shape.x, row * shape.x);
+                }
+            }
+            NetworkStyle::Graph => {
+                // Oldest sample on the left, newest on the right
+                let half = shape.y / 2;
+                for (x, (rx, tx)) in self.history.iter().enumerate() {
+                    let mut column: Vec<&mut u8> = out.iter_mut().skip(x).step_by(shape.x).collect();
+                    let (upper, lower) = column.split_at_mut(half);
+                    render_vbar(upper, self.scale(*rx), ON_FULL, false);
+                    render_vbar(lower, self.scale(*tx), ON_DIM, true);
                 }
             }
         }
@@ -357,73 +920,990 @@ impl UpdatableWidget for AllCPUsWidget {
     }
 
     fn get_shape(&self) -> Shape {
-        return match self.merge_threads {
-            false => Shape {
+        match self.style {
+            NetworkStyle::Bars => Shape {
                 x: 9,
-                y: self.cpu_usages.len(),
+                y: self.height * 2,
+            },
+            NetworkStyle::Graph => Shape {
+                x: NET_HISTORY,
+                y: self.height,
+            },
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+/// -------- Temperature Widget --------
+/// How TemperatureWidget shows a sensor that's above its critical temperature
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CriticalStyle {
+    /// Flash the bar on and off every update
+    #[default]
+    Blink,
+    /// Draw the bar at full brightness
+    Brighten,
+}
+
+/// Create a widget that displays sensor temperatures as bars between a minimum
+/// and maximum, optionally followed by a dimmer bar per fan showing its speed
+pub struct TemperatureWidget {
+    /// Sensor labels to show (matched ignoring case, e.g. "cpu" matches "k10temp
+    /// Tctl"). Only the hottest sensor is shown if empty.
+    sensors: Vec<String>,
+    /// Temperature shown as an empty bar, in degrees C
+    min: f32,
+    /// Temperature shown as a full bar, in degrees C
+    max: f32,
+    /// Temperature above which a sensor is highlighted. The sensor's own
+    /// critical temperature is used if not given.
+    critical: Option<f32>,
+    critical_style: CriticalStyle,
+    /// Fan speed shown as a full bar, in RPM
+    fan_max: f32,
+    bar_height: usize,
+    /// (temperature, critical temperature) of each bar, None if the sensor wasn't found
+    temperatures: Vec<Option<(f32, Option<f32>)>>,
+    /// Fan sysfs files found when the widget was created, with their latest speed
+    fans: Vec<(PathBuf, f32)>,
+    /// Whether blinking bars are currently lit
+    blink_on: bool,
+    components: sysinfo::Components,
+}
+
+impl TemperatureWidget {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensors: Vec<String>,
+        min: f32,
+        max: f32,
+        critical: Option<f32>,
+        critical_style: CriticalStyle,
+        fans: bool,
+        fan_max: f32,
+        bar_height: usize,
+    ) -> TemperatureWidget {
+        println!("Initializing TemperatureWidget");
+        let rows = sensors.len().max(1);
+        let mut widget = TemperatureWidget {
+            sensors,
+            min,
+            max: max.max(min + 1.0),
+            critical,
+            critical_style,
+            fan_max: fan_max.max(1.0),
+            bar_height: bar_height.max(1),
+            temperatures: vec![None; rows],
+            fans: match fans {
+                true => Self::find_fans().into_iter().map(|f| (f, 0.0)).collect(),
+                false => vec![],
+            },
+            blink_on: true,
+            components: sysinfo::Components::new_with_refreshed_list(),
+        };
+        widget.update();
+        widget
+    }
+
+    ///
+    /// Find every fan speed file in hwmon sysfs. The set of fans is fixed when the
+    /// widget is created, so its size doesn't change while running.
+    ///
+    #[cfg(target_os = "linux")]
+    fn find_fans() -> Vec<PathBuf> {
+        let mut fans: Vec<PathBuf> = std::fs::read_dir("/sys/class/hwmon")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(|hwmon| std::fs::read_dir(hwmon.path()).into_iter().flatten().flatten())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.starts_with("fan") && name.ends_with("_input")
+            })
+            .collect();
+        fans.sort();
+        fans
+    }
+
+    /// Fan speeds are only read from hwmon sysfs, so there are no fans elsewhere
+    #[cfg(not(target_os = "linux"))]
+    fn find_fans() -> Vec<PathBuf> {
+        vec![]
+    }
+}
+
+impl UpdatableWidget for TemperatureWidget {
+    fn update(&mut self) {
+        self.components.refresh();
+        let reading = |c: &sysinfo::Component| (c.temperature(), c.critical());
+
+        self.temperatures = if self.sensors.is_empty() {
+            vec![self
+                .components
+                .iter()
+                .map(reading)
+                .filter(|(t, _)| !t.is_nan())
+                .max_by(|a, b| a.0.total_cmp(&b.0))]
+        } else {
+            self.sensors
+                .iter()
+                .map(|sel| {
+                    let sel = sel.to_lowercase();
+                    self.components
+                        .iter()
+                        .find(|c| c.label().to_lowercase().contains(&sel))
+                        .map(reading)
+                })
+                .collect()
+        };
+
+        // A fan that can't be read shows as stopped
+        for (path, rpm) in self.fans.iter_mut() {
+            *rpm = std::fs::read_to_string(path)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(0.0);
+        }
+
+        self.blink_on = !self.blink_on;
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let width = self.get_shape().x;
+        let mut out: Vec<u8> = vec![OFF; width * self.get_shape().y];
+
+        let temperature_rows = self.temperatures.iter().map(|reading| match reading {
+            Some((temp, sensor_critical)) => {
+                let fraction = (temp - self.min) / (self.max - self.min);
+                let brightness = match self.critical.or(*sensor_critical) {
+                    Some(critical) if *temp >= critical => match self.critical_style {
+                        CriticalStyle::Blink if self.blink_on => ON_FULL,
+                        CriticalStyle::Blink => OFF,
+                        CriticalStyle::Brighten => 255,
+                    },
+                    _ => ON_FULL,
+                };
+                Some((fraction, brightness))
+            }
+            None => None,
+        });
+        let fan_rows = self
+            .fans
+            .iter()
+            .map(|(_, rpm)| Some((rpm / self.fan_max, ON_DIM)));
+
+        for (idx, row) in temperature_rows.chain(fan_rows).enumerate() {
+            let start = idx * self.bar_height * width;
+            match row {
+                Some((fraction, brightness)) => render_bar(
+                    &mut out[start..start + width],
+                    fraction.clamp(0.0, 1.0),
+                    brightness,
+                ),
+                None => render_missing(&mut out[start..start + width]),
+            }
+            for row in 1..self.bar_height {
+                out.copy_within(start..start + width, start + row * width);
+            }
+        }
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: 9,
+            y: (self.temperatures.len() + self.fans.len()) * self.bar_height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+/// -------- Text Widget --------
+/// Where TextWidget gets its text from
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    /// A fixed string, e.g. `{"text": "hello"}`
+    Text(String),
+    /// A file that is re-read while running, e.g. `{"file": "/tmp/status"}`
+    File(PathBuf),
+    /// The latest line written to stdin, e.g. `"stdin"`
+    Stdin,
+}
+
+/// Which way TextWidget scrolls
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollDirection {
+    /// Upright text scrolling right to left
+    #[default]
+    Horizontal,
+    /// Text rotated a quarter turn clockwise, scrolling bottom to top
+    Vertical,
+}
+
+/// How often a text file is re-read
+const TEXT_REREAD_PERIOD: Duration = Duration::from_secs(1);
+
+/// Create a widget that scrolls a line of text through its area. Text that fits
+/// is drawn without scrolling.
+pub struct TextWidget {
+    source: TextSource,
+    direction: ScrollDirection,
+    font: FontFace,
+    spacing: usize,
+    /// Size of the widget along the direction it scrolls
+    length: usize,
+    /// Blank pixels between the end of the text and the start of the next repeat
+    gap: usize,
+    /// Pixels scrolled per second
+    speed: f32,
+    text: String,
+    /// The rendered text, upright
+    rendered: (Shape, Vec<u8>),
+    /// When the text was last changed, which is where scrolling starts from
+    scroll_start: Instant,
+    /// When the file was last re-read
+    last_read: Option<Instant>,
+    /// Latest line read from stdin, filled in by a background thread
+    stdin_line: Option<Arc<Mutex<String>>>,
+}
+
+///
+/// The latest line written to stdin. Stdin is locked by whichever thread reads it,
+/// so a single reader is started the first time this is called and shared by
+/// every widget after it, including ones rebuilt when the layout is reloaded.
+///
+fn stdin_line() -> Arc<Mutex<String>> {
+    static LINE: OnceLock<Arc<Mutex<String>>> = OnceLock::new();
+    LINE.get_or_init(|| {
+        let line = Arc::new(Mutex::new(String::new()));
+        let writer = line.clone();
+        thread::spawn(move || {
+            for l in std::io::stdin().lines().map_while(Result::ok) {
+                *writer.lock().unwrap() = l;
+            }
+        });
+        line
+    })
+    .clone()
+}
+
+impl TextWidget {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        source: TextSource,
+        direction: ScrollDirection,
+        font: FontFace,
+        spacing: usize,
+        length: Option<usize>,
+        gap: Option<usize>,
+        speed: f32,
+    ) -> TextWidget {
+        println!("Initializing TextWidget");
+
+        let length = length.unwrap_or(match direction {
+            ScrollDirection::Horizontal => 9,
+            ScrollDirection::Vertical => 34,
+        });
+
+        let stdin_line = matches!(source, TextSource::Stdin).then(stdin_line);
+
+        let mut widget = TextWidget {
+            source,
+            direction,
+            font,
+            spacing,
+            length: length.max(1),
+            gap: gap.unwrap_or(length),
+            speed: speed.max(0.1),
+            text: String::new(),
+            rendered: render_text("", font, spacing),
+            scroll_start: Instant::now(),
+            last_read: None,
+            stdin_line,
+        };
+        if let TextSource::Text(ref text) = widget.source {
+            widget.set_text(text.clone());
+        }
+        widget.update();
+        widget
+    }
+
+    ///
+    /// Show new text, restarting the scroll if it has changed. Only the first
+    /// line is shown, with surrounding whitespace removed.
+    ///
+    fn set_text(&mut self, text: String) {
+        let text = text.lines().next().unwrap_or("").trim().to_string();
+        if text != self.text {
+            self.rendered = render_text(&text, self.font, self.spacing);
+            self.text = text;
+            self.scroll_start = Instant::now();
+        }
+    }
+
+    ///
+    /// The pixel of the upright text shown at a position along the scroll
+    /// direction (pos) and across it (row), or OFF past the end of the text
+    ///
+    fn text_pixel(&self, pos: usize, row: usize) -> u8 {
+        let (shape, ref pixels) = self.rendered;
+        let offset = if shape.x <= self.length {
+            0
+        } else {
+            let scrolled = (self.scroll_start.elapsed().as_secs_f32() * self.speed) as usize;
+            scrolled % (shape.x + self.gap)
+        };
+
+        let x = (offset + pos) % (shape.x + self.gap).max(1);
+        match x < shape.x && shape.x > 0 {
+            true => pixels[row * shape.x + x],
+            false => OFF,
+        }
+    }
+}
+
+impl UpdatableWidget for TextWidget {
+    fn update(&mut self) {
+        match self.source {
+            TextSource::Text(_) => {}
+            TextSource::File(ref path) => {
+                if self.last_read.is_some_and(|t| t.elapsed() < TEXT_REREAD_PERIOD) {
+                    return;
+                }
+                self.last_read = Some(Instant::now());
+                // A missing file shows as blank, it may be created later
+                let text = std::fs::read_to_string(path).unwrap_or_default();
+                self.set_text(text);
+            }
+            TextSource::Stdin => {
+                let line = self.stdin_line.as_ref().unwrap().lock().unwrap().clone();
+                self.set_text(line);
+            }
+        }
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let shape = self.get_shape();
+        let mut out = vec![OFF; shape.x * shape.y];
+        let height = self.font.height();
+
+        for pos in 0..self.length {
+            for row in 0..height {
+                let px = self.text_pixel(pos, row);
+                match self.direction {
+                    ScrollDirection::Horizontal => out[row * shape.x + pos] = px,
+                    // Rotated clockwise, so the top of the text faces right
+                    ScrollDirection::Vertical => out[pos * shape.x + (height - 1 - row)] = px,
+                }
+            }
+        }
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        match self.direction {
+            ScrollDirection::Horizontal => Shape {
+                x: self.length,
+                y: self.font.height(),
+            },
+            ScrollDirection::Vertical => Shape {
+                x: self.font.height(),
+                y: self.length,
             },
-            true => Shape { x: 8, y: 8 },
+        }
+    }
+
+    /// Update once per pixel scrolled
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.speed)
+    }
+}
+
+/// -------- History Widget --------
+/// Create a widget that draws a scrolling graph of a metric over time, one
+/// column per sample with the newest on the right
+pub struct HistoryWidget {
+    source: MetricSource,
+    /// Samples as fractions of full scale, oldest first, one per column
+    history: VecDeque<f32>,
+    height: usize,
+}
+
+impl HistoryWidget {
+    pub fn new(source: MetricSource, width: usize, height: usize) -> HistoryWidget {
+        println!("Initializing HistoryWidget");
+        HistoryWidget {
+            source,
+            history: VecDeque::from(vec![0.0; width.max(1)]),
+            height: height.max(1),
+        }
+    }
+}
+
+impl UpdatableWidget for HistoryWidget {
+    fn update(&mut self) {
+        self.history.pop_front();
+        self.history.push_back(self.source.sample());
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        let width = self.history.len();
+        let mut out: Vec<u8> = vec![OFF; width * self.height];
+
+        // The top of each column is lit partially, so small changes still show
+        for (x, sample) in self.history.iter().enumerate() {
+            let mut column: Vec<&mut u8> = out.iter_mut().skip(x).step_by(width).collect();
+            render_vbar(&mut column, *sample, ON_FULL, false);
+        }
+
+        out
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: self.history.len(),
+            y: self.height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+/// -------- Command Widget --------
+/// A program run by CommandWidget, either through the shell or directly
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum CommandLine {
+    /// A command line run by the shell, e.g. `"cat /sys/class/drm/card0/device/gpu_busy_percent"`
+    Shell(String),
+    /// A program and its arguments, run without a shell, e.g. `["nvidia-smi", "--query-gpu=utilization.gpu"]`
+    Args(Vec<String>),
+}
+
+impl CommandLine {
+    fn spawn(&self) -> std::io::Result<Child> {
+        let mut command = match self {
+            #[cfg(windows)]
+            CommandLine::Shell(line) => {
+                use std::os::windows::process::CommandExt;
+
+                // cmd doesn't undo the quoting Command would add, so pass the line as written
+                let mut command = Command::new("cmd");
+                command.arg("/C").raw_arg(line);
+                command
+            }
+            #[cfg(not(windows))]
+            CommandLine::Shell(line) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(line);
+                command
+            }
+            CommandLine::Args(args) => {
+                let (program, args) = args.split_first().ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "No program given")
+                })?;
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+        };
+
+        // Errors from the program end up alongside ours
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandLine::Shell(line) => write!(f, "{}", line),
+            CommandLine::Args(args) => write!(f, "{}", args.join(" ")),
+        }
+    }
+}
+
+/// How CommandWidget reads the output of its program
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandOutput {
+    /// A number, drawn as a bar between a minimum and maximum, e.g. `42`
+    #[default]
+    Number,
+    /// The first line, drawn as text from the left edge
+    Text,
+    /// A row of pixel brightnesses (0-255) per line, separated by spaces or commas
+    Pixels,
+}
+
+/// How long to wait for a program to finish before drawing, so quick programs
+/// are shown straight away rather than on the next update
+const COMMAND_QUICK_WAIT: Duration = Duration::from_millis(100);
+
+/// A run of the program that hasn't been read yet
+struct CommandRun {
+    child: Child,
+    /// Everything printed so far, filled in by a background thread so the
+    /// program never blocks on a full pipe
+    stdout: thread::JoinHandle<std::io::Result<String>>,
+    started: Instant,
+}
+
+/// Create a widget that runs a program on every update and draws what it
+/// prints as a bar, text or pixels. Programs that fail, print something that
+/// can't be read, or take longer than the timeout are drawn as diagonal stripes.
+pub struct CommandWidget {
+    command: CommandLine,
+    output: CommandOutput,
+    /// Number drawn as an empty bar
+    min: f32,
+    /// Number drawn as a full bar
+    max: f32,
+    timeout: Duration,
+    font: FontFace,
+    spacing: usize,
+    width: usize,
+    height: usize,
+    running: Option<CommandRun>,
+    /// Pixels from the last run that finished, or why it failed
+    result: Result<Vec<u8>, String>,
+}
+
+impl CommandWidget {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        command: CommandLine,
+        output: CommandOutput,
+        min: f32,
+        max: f32,
+        timeout: Duration,
+        font: FontFace,
+        spacing: usize,
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> CommandWidget {
+        println!("Initializing CommandWidget");
+
+        let width = width.unwrap_or(9).max(1);
+        let height = height.unwrap_or(match output {
+            CommandOutput::Number => 1,
+            CommandOutput::Text => font.height(),
+            CommandOutput::Pixels => 9,
+        });
+
+        let mut widget = CommandWidget {
+            command,
+            output,
+            min,
+            max,
+            timeout,
+            font,
+            spacing,
+            width,
+            height: height.max(1),
+            running: None,
+            result: Ok(vec![OFF; width * height.max(1)]),
+        };
+        widget.update();
+        widget
+    }
+
+    ///
+    /// Start the program, or record why it couldn't be started
+    ///
+    fn start(&mut self) {
+        match self.command.spawn() {
+            Ok(mut child) => {
+                let mut stdout = child.stdout.take().unwrap();
+                let stdout = thread::spawn(move || {
+                    let mut out = String::new();
+                    stdout.read_to_string(&mut out).map(|_| out)
+                });
+                self.running = Some(CommandRun {
+                    child,
+                    stdout,
+                    started: Instant::now(),
+                });
+            }
+            Err(e) => self.set_result(Err(format!("Unable to run: {}", e))),
+        }
+    }
+
+    ///
+    /// Check on the running program, reading its output if it has finished and
+    /// killing it if it has taken too long. Returns true once it's no longer running.
+    ///
+    fn poll(&mut self) -> bool {
+        let Some(ref mut run) = self.running else {
+            return true;
+        };
+
+        let result = match run.child.try_wait() {
+            Ok(Some(status)) if run.stdout.is_finished() => {
+                let run = self.running.take().unwrap();
+                match (status.success(), run.stdout.join().unwrap()) {
+                    (true, Ok(out)) => self.parse(&out),
+                    (true, Err(e)) => Err(format!("Unable to read the output: {}", e)),
+                    (false, _) => Err(format!("Exited with {}", status)),
+                }
+            }
+            Err(e) => {
+                self.running = None;
+                Err(format!("Unable to check on the program: {}", e))
+            }
+            _ if run.started.elapsed() > self.timeout => {
+                // The output thread is left to finish on its own, in case the
+                // program started something else that still holds the pipe
+                let _ = run.child.kill();
+                let _ = run.child.wait();
+                self.running = None;
+                Err(format!("Timed out after {:?}", self.timeout))
+            }
+            _ => return false,
         };
+
+        self.set_result(result);
+        true
+    }
+
+    ///
+    /// Turn the output of the program into pixels
+    ///
+    fn parse(&self, out: &str) -> Result<Vec<u8>, String> {
+        let mut pixels = vec![OFF; self.width * self.height];
+        match self.output {
+            CommandOutput::Number => {
+                let token = out.split_whitespace().next().unwrap_or("");
+                let value: f32 = token
+                    .trim_end_matches('%')
+                    .parse()
+                    .map_err(|_| format!("Expected a number, got \"{}\"", token))?;
+                let fraction = ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+                for row in pixels.chunks_mut(self.width) {
+                    render_bar(row, fraction, ON_FULL);
+                }
+            }
+            CommandOutput::Text => {
+                let line = out.lines().next().unwrap_or("").trim();
+                let (shape, text) = render_text(line, self.font, self.spacing);
+                // Text that doesn't fit is cut off on the right
+                for y in 0..shape.y.min(self.height) {
+                    for x in 0..shape.x.min(self.width) {
+                        pixels[y * self.width + x] = text[y * shape.x + x];
+                    }
+                }
+            }
+            CommandOutput::Pixels => {
+                // Missing pixels are off, and anything past the edge is ignored
+                for (y, line) in out.lines().take(self.height).enumerate() {
+                    let values = line.split(|c: char| c == ',' || c.is_whitespace());
+                    let values = values.filter(|v| !v.is_empty()).take(self.width);
+                    for (x, value) in values.enumerate() {
+                        pixels[y * self.width + x] = value.parse().map_err(|_| {
+                            format!("Expected a brightness from 0 to 255, got \"{}\"", value)
+                        })?;
+                    }
+                }
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    ///
+    /// Show the result of a run, printing errors when they first appear rather
+    /// than on every update
+    ///
+    fn set_result(&mut self, result: Result<Vec<u8>, String>) {
+        if let Err(ref e) = result {
+            if self.result.as_ref().err() != Some(e) {
+                println!("Command \"{}\" failed: {}", self.command, e);
+            }
+        }
+        self.result = result;
+    }
+}
+
+impl UpdatableWidget for CommandWidget {
+    fn update(&mut self) {
+        // A program that's still running within its timeout is left to finish,
+        // otherwise the next run is started
+        if !self.poll() {
+            return;
+        }
+        self.start();
+
+        let started = Instant::now();
+        while started.elapsed() < COMMAND_QUICK_WAIT && !self.poll() {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn get_matrix(&self) -> Vec<u8> {
+        match self.result {
+            Ok(ref pixels) => pixels.clone(),
+            Err(_) => (0..self.width * self.height)
+                .map(|i| match (i % self.width + i / self.width) % 3 {
+                    0 => ON_DIM,
+                    _ => OFF,
+                })
+                .collect(),
+        }
+    }
+
+    fn get_shape(&self) -> Shape {
+        Shape {
+            x: self.width,
+            y: self.height,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(5)
     }
 }
 
+/// -------- Clock Widget --------
+/// Whether ClockWidget shows hours from 0-23 or 1-12
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum HourFormat {
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFour,
+    /// 12 hour time, with a dot next to the hours at the top for AM or the
+    /// bottom for PM (or an extra bit lit for PM in the binary style)
+    #[serde(rename = "12h")]
+    Twelve,
+}
+
+/// How ClockWidget shows seconds
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecondsDisplay {
+    #[default]
+    None,
+    /// A bar below the time that fills up over each minute
+    Bar,
+    /// Two more digits below the time
+    Digits,
+}
+
+/// What ClockWidget shows
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockView {
+    /// Hours above minutes
+    #[default]
+    Time,
+    /// Day of the month above the month
+    Date,
+}
+
+/// How ClockWidget draws numbers
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockStyle {
+    /// 3x5 digits
+    #[default]
+    Digits,
+    /// A row of bits each for hours, minutes (and seconds), the lowest bit on the right
+    Binary,
+    /// A column of 4 bits for each digit, the lowest bit at the bottom
+    Bcd,
+}
+
+/// Every option for ClockWidget
+#[derive(Clone, Copy)]
+pub struct ClockOptions {
+    pub format: HourFormat,
+    pub seconds: SecondsDisplay,
+    pub view: ClockView,
+    pub style: ClockStyle,
+    /// The timezone to show the time in, or the system's local time if None
+    pub timezone: Option<chrono_tz::Tz>,
+}
+
+/// Brightness of bits that are off in the binary and BCD styles, so the
+/// positions can still be seen
+const CLOCK_BIT_OFF: u8 = ON_DIM / 4;
+
+/// Create a widget that displays the time or date
 pub struct ClockWidget {
-    time: chrono::DateTime<Local>,
+    options: ClockOptions,
+    time: NaiveDateTime,
 }
 
 impl ClockWidget {
-    pub fn new() -> Self {
+    pub fn new(options: ClockOptions) -> Self {
         println!("Initializing ClockWidget");
-        let dt = chrono::offset::Local::now();
-        Self { time: dt }
+        let mut widget = Self {
+            options,
+            time: NaiveDateTime::default(),
+        };
+        widget.update();
+        widget
     }
 
-    fn render_digit(num: u32) -> &'static [u8] {
-        match num {
-            0 => DIGIT_0,
-            1 => DIGIT_1,
-            2 => DIGIT_2,
-            3 => DIGIT_3,
-            4 => DIGIT_4,
-            5 => DIGIT_5,
-            6 => DIGIT_6,
-            7 => DIGIT_7,
-            8 => DIGIT_8,
-            9 => DIGIT_9,
-            _ => DIGIT_0,
+    ///
+    /// Render a two digit number, centred in a 9 pixel wide row
+    ///
+    fn render_number(num: u32) -> Vec<u8> {
+        let (shape, text) = render_text(&format!("{:02}", num % 100), FontFace::Small, 1);
+        let mut numrow = vec![OFF; 9 * shape.y];
+        for (y, row) in text.chunks(shape.x).enumerate() {
+            numrow[y * 9 + 1..y * 9 + 1 + shape.x].copy_from_slice(row);
         }
+        numrow
     }
 
-    fn render_number(num: u32) -> Vec<u8> {
-        let mut numrow = vec![0; 9 * 5];
-        let first_digit = Self::render_digit(num / 10);
-        let second_digit = Self::render_digit(num % 10);
-        for idx in 0..(9 * 5) {
-            let cell = match idx % 9 {
-                1 | 2 | 3 => first_digit[((idx / 9) * 3) + (idx % 9) - 1],
-                5 | 6 | 7 => second_digit[((idx / 9) * 3) + idx % 9 - 5],
-                _ => OFF,
+    ///
+    /// The numbers to draw, from top to bottom, along with whether it's PM
+    ///
+    fn numbers(&self) -> (Vec<u32>, bool) {
+        let pm = self.time.hour() >= 12;
+        let mut numbers = match self.options.view {
+            ClockView::Date => return (vec![self.time.day(), self.time.month()], pm),
+            ClockView::Time => vec![self.time.hour(), self.time.minute()],
+        };
+        if self.options.format == HourFormat::Twelve {
+            numbers[0] = match self.time.hour() % 12 {
+                0 => 12,
+                hour => hour,
             };
-            numrow[idx] = cell;
         }
-        numrow
+        if self.options.seconds == SecondsDisplay::Digits {
+            numbers.push(self.time.second());
+        }
+        (numbers, pm)
+    }
+
+    ///
+    /// Whether to draw the AM/PM dot
+    ///
+    fn shows_am_pm(&self) -> bool {
+        self.options.format == HourFormat::Twelve && self.options.view == ClockView::Time
+    }
+
+    ///
+    /// Whether to draw the seconds bar
+    ///
+    fn shows_seconds_bar(&self) -> bool {
+        self.options.seconds == SecondsDisplay::Bar && self.options.view == ClockView::Time
+    }
+
+    ///
+    /// Height of the numbers, not counting the seconds bar
+    ///
+    fn numbers_height(&self) -> usize {
+        let count = self.numbers().0.len();
+        match self.options.style {
+            ClockStyle::Digits => count * 6 - 1,
+            ClockStyle::Binary => count * 2 - 1,
+            ClockStyle::Bcd => 4,
+        }
     }
 }
 
 impl UpdatableWidget for ClockWidget {
     fn update(&mut self) {
-        self.time = chrono::offset::Local::now();
+        self.time = match self.options.timezone {
+            Some(tz) => Utc::now().with_timezone(&tz).naive_local(),
+            None => chrono::offset::Local::now().naive_local(),
+        };
     }
 
     fn get_matrix(&self) -> Vec<u8> {
-        let mut matrix = Vec::with_capacity(9 * 11);
-        matrix.extend(Self::render_number(self.time.hour()));
-        matrix.extend(vec![OFF; 9]);
-        matrix.extend(Self::render_number(self.time.minute()));
+        let shape = self.get_shape();
+        let mut matrix = Vec::with_capacity(shape.x * shape.y);
+        let (numbers, pm) = self.numbers();
+
+        match self.options.style {
+            ClockStyle::Digits => {
+                for (idx, num) in numbers.iter().enumerate() {
+                    if idx > 0 {
+                        matrix.extend(vec![OFF; 9]);
+                    }
+                    matrix.extend(Self::render_number(*num));
+                }
+            }
+            ClockStyle::Binary => {
+                for (idx, num) in numbers.iter().enumerate() {
+                    if idx > 0 {
+                        matrix.extend(vec![OFF; 9]);
+                    }
+                    // Six bits is enough for anything up to 63, right aligned
+                    // and leaving room for the AM/PM dot
+                    matrix.extend(vec![OFF; 2]);
+                    for bit in (0..6).rev() {
+                        matrix.push(if num & (1 << bit) != 0 { ON_FULL } else { CLOCK_BIT_OFF });
+                    }
+                    matrix.push(OFF);
+                }
+            }
+            ClockStyle::Bcd => {
+                // Pairs of digit columns with a gap between each number
+                matrix.extend(vec![OFF; 9 * 4]);
+                for (idx, num) in numbers.iter().enumerate() {
+                    for (col, digit) in [num / 10, num % 10].into_iter().enumerate() {
+                        for bit in 0..4 {
+                            matrix[(3 - bit) * 9 + idx * 3 + col] =
+                                if digit & (1 << bit) != 0 { ON_FULL } else { CLOCK_BIT_OFF };
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.shows_am_pm() {
+            match self.options.style {
+                // An extra bit after the hours, lit for PM
+                ClockStyle::Binary => matrix[8] = if pm { ON_FULL } else { CLOCK_BIT_OFF },
+                // A dot on the right of the hours, at the top for AM and the bottom for PM
+                ClockStyle::Digits | ClockStyle::Bcd => {
+                    let hour_rows = if self.options.style == ClockStyle::Digits { 5 } else { 4 };
+                    let row = if pm { hour_rows - 1 } else { 0 };
+                    matrix[row * 9 + 8] = ON_FULL;
+                }
+            }
+        }
+
+        if self.shows_seconds_bar() {
+            matrix.extend(vec![OFF; 9]);
+            let mut bar = vec![OFF; 9];
+            let seconds = self.time.second() as f32 + self.time.nanosecond() as f32 / 1e9;
+            render_bar(&mut bar, seconds / 60.0, ON_DIM);
+            matrix.extend(bar);
+        }
+
         matrix
     }
 
     fn get_shape(&self) -> Shape {
-        return Shape { x: 9, y: 11 };
+        let bar = if self.shows_seconds_bar() { 2 } else { 0 };
+        Shape {
+            x: 9,
+            y: self.numbers_height() + bar,
+        }
+    }
+
+    fn update_interval(&self) -> Duration {
+        Duration::from_secs(1)
     }
 }